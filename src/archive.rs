@@ -0,0 +1,362 @@
+//! Books inside `.zip` / `.tar` (`.tar.gz`, `.tgz`) bundles, for
+//! `load --descend-archives`. Nothing is extracted next to the bundle: each
+//! member is streamed into an anonymous temp file (XXH3 computed on the way,
+//! as `remote::fetch` does), and metadata and the other hashes are read from
+//! there, so memory use does not grow with member size.
+//!
+//! Later commands read members through `Members`, which decompresses each
+//! tarball once per command (tar has no index to seek to a member).
+//!
+//! A member's `full_path` is `<archive path>!/<member path>`, its `protocol`
+//! is `zip` or `tar`, and its `uri_path` reads
+//! `file:///…/bundle.zip!/inner/book.epub`.
+
+use anyhow::{Context, Result, anyhow};
+use flate2::read::GzDecoder;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, warn};
+use zip::ZipArchive;
+
+use crate::fb2;
+use crate::hash;
+use crate::metadata;
+use crate::model::{BookEntry, FileFormat};
+use crate::remote;
+use crate::report;
+use crate::util::{file_uri, now_iso8601};
+
+/// Separates the archive path from the member path in `full_path`.
+pub const MEMBER_SEP: &str = "!/";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    /// Kind of a bundle by file name. `.fb2.zip` is a book, not a bundle.
+    pub fn of(p: &Path) -> Option<ArchiveKind> {
+        let name = p.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".zip") && !fb2::is_fb2_zip(p) {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+
+    pub fn protocol(self) -> &'static str {
+        match self {
+            ArchiveKind::Zip => "zip",
+            ArchiveKind::Tar | ArchiveKind::TarGz => "tar",
+        }
+    }
+}
+
+/// True for entries that live inside a bundle.
+pub fn is_member(e: &BookEntry) -> bool {
+    matches!(e.protocol.as_str(), "zip" | "tar")
+}
+
+/// (archive path, member path) of a member entry.
+pub fn split(e: &BookEntry) -> Option<(&str, &str)> {
+    if !is_member(e) {
+        return None;
+    }
+    e.full_path.split_once(MEMBER_SEP)
+}
+
+/// The file on disk that holds an entry: the bundle for members.
+pub fn disk_path(e: &BookEntry) -> PathBuf {
+    match split(e) {
+        Some((archive, _)) => PathBuf::from(archive),
+        None => PathBuf::from(&e.full_path),
+    }
+}
+
+/// Index every book inside `archive`. Unreadable members are logged and skipped.
+pub fn build_entries(archive: &Path, no_hash: bool, quick_only: bool) -> Vec<BookEntry> {
+    let Some(kind) = ArchiveKind::of(archive) else {
+        return Vec::new();
+    };
+    let mut out = Vec::new();
+    let walked = for_each_book(
+        archive,
+        kind,
+        |_| true,
+        |name, m| {
+            out.push(member_entry(archive, kind, name, &m, no_hash, quick_only));
+        },
+    );
+    if let Err(e) = walked {
+        warn!("archive {}: {:#}", archive.display(), e);
+        report::file_error(archive.display(), &e);
+    }
+    out
+}
+
+/// One member's bytes, spooled to an anonymous temp file (removed on drop).
+pub struct Member {
+    file: File,
+    pub size: u64,
+    /// XXH3 of the bytes, computed while spooling
+    pub xxhash: u128,
+}
+
+impl Member {
+    fn spool<R: Read>(r: R) -> Result<Member> {
+        let (file, size, xxhash) = remote::spool(r)?;
+        Ok(Member { file, size, xxhash })
+    }
+
+    /// Buffered reader over the bytes, from the start.
+    pub fn reader(&self) -> io::Result<BufReader<&File>> {
+        let mut f = &self.file;
+        f.rewind()?;
+        Ok(BufReader::new(f))
+    }
+
+    /// (xxhash, quick_hash, content_hash), honoring `--no-hash` /
+    /// `--quick-hash` like `load`.
+    pub fn hashes(
+        &self,
+        format: FileFormat,
+        no_hash: bool,
+        quick_only: bool,
+    ) -> (Option<u128>, Option<u128>, Option<u128>) {
+        if no_hash {
+            return (None, None, None);
+        }
+        let quick = self
+            .reader()
+            .ok()
+            .and_then(|r| hash::quick_hash_reader(r, self.size).ok());
+        if quick_only {
+            return (None, quick, None);
+        }
+        let content = self
+            .reader()
+            .ok()
+            .and_then(|r| hash::content_hash_reader(r, format));
+        (Some(self.xxhash), quick, content)
+    }
+}
+
+/// Fresh entry for one member. Mirrors `load::build_entry`.
+pub fn member_entry(
+    archive: &Path,
+    kind: ArchiveKind,
+    member: &str,
+    m: &Member,
+    no_hash: bool,
+    quick_only: bool,
+) -> BookEntry {
+    let name = Path::new(member);
+    let format = FileFormat::from_path(name).unwrap_or_default();
+    let meta = m
+        .reader()
+        .map_err(anyhow::Error::from)
+        .and_then(|r| metadata::read_metadata(r, name, format))
+        .unwrap_or_default();
+    let (xxhash, quick_hash, content_hash) = m.hashes(format, no_hash, quick_only);
+    let full_path = format!("{}{MEMBER_SEP}{member}", archive.to_string_lossy());
+
+    BookEntry {
+        uri_path: file_uri(Path::new(&full_path)),
+        full_path,
+        root: None,
+        rel_path: None,
+        volume: None,
+        protocol: kind.protocol().into(),
+        filename: name
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+            .to_string(),
+        xxhash,
+        content_hash,
+        quick_hash,
+        etag: None,
+        date_found: now_iso8601(),
+        missing: false,
+        stale: false,
+        size_bytes: m.size,
+        format,
+        validation: None,
+        validation_issues: Vec::new(),
+        drm: meta.drm,
+        title: meta.title,
+        author: meta.author,
+        description: meta.description,
+        chapters: meta.chapters,
+        publish_date: meta.publish_date,
+        publisher: meta.publisher,
+        other_metadata: meta.other_metadata,
+    }
+}
+
+/// Tarballs spooled so far: bundle path → members not handed out yet.
+type Spooled = Arc<Mutex<Option<HashMap<String, Member>>>>;
+
+/// Reads bundle members for one command. Zip members are read directly;
+/// the first read from a tarball decompresses it once and spools every
+/// member the command will ask for (those of the entries given to `new`).
+#[derive(Default)]
+pub struct Members {
+    wanted: HashMap<String, HashSet<String>>,
+    tars: Mutex<HashMap<String, Spooled>>,
+}
+
+impl Members {
+    pub fn new<'a>(entries: impl IntoIterator<Item = &'a BookEntry>) -> Members {
+        let mut wanted: HashMap<String, HashSet<String>> = HashMap::new();
+        for (archive, member) in entries.into_iter().filter_map(split) {
+            wanted
+                .entry(archive.to_string())
+                .or_default()
+                .insert(member.to_string());
+        }
+        Members {
+            wanted,
+            tars: Mutex::default(),
+        }
+    }
+
+    /// The bytes of a member entry. Each tarball member is handed out once;
+    /// asking again walks the tarball for that member alone.
+    pub fn read(&self, e: &BookEntry) -> Result<Member> {
+        let (archive, member) = split(e).ok_or_else(|| anyhow!("not an archive member"))?;
+        let path = Path::new(archive);
+        let kind =
+            ArchiveKind::of(path).ok_or_else(|| anyhow!("not a bundle: {}", path.display()))?;
+        if kind == ArchiveKind::Zip {
+            let mut zip = open_zip(path)?;
+            let f = zip
+                .by_name(member)
+                .with_context(|| format!("{member} not in {}", path.display()))?;
+            return Member::spool(f);
+        }
+
+        let slot = Arc::clone(
+            self.tars
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .entry(archive.to_string())
+                .or_default(),
+        );
+        // Other threads asking for the same tarball wait for this one pass
+        let mut slot = slot.lock().unwrap_or_else(|e| e.into_inner());
+        let spooled = slot.get_or_insert_with(|| {
+            let mut spooled = HashMap::new();
+            if let Some(wanted) = self.wanted.get(archive) {
+                debug!(
+                    "archive {}: reading {} member(s) in one pass",
+                    path.display(),
+                    wanted.len()
+                );
+                let walked = for_each_book(
+                    path,
+                    kind,
+                    |name| wanted.contains(name),
+                    |name, m| {
+                        spooled.insert(name.to_string(), m);
+                    },
+                );
+                if let Err(e) = walked {
+                    debug!("archive {}: {:#}", path.display(), e);
+                }
+            }
+            spooled
+        });
+        if let Some(m) = spooled.remove(member) {
+            return Ok(m);
+        }
+        drop(slot);
+        debug!("archive {}: reading {} alone", path.display(), member);
+        let mut found = None;
+        for_each_book(path, kind, |name| name == member, |_, m| found = Some(m))?;
+        found.with_context(|| format!("{member} not in {}", path.display()))
+    }
+}
+
+/// Call `f(member path, member)` for each member with an indexed extension
+/// that `want` accepts.
+fn for_each_book(
+    archive: &Path,
+    kind: ArchiveKind,
+    want: impl Fn(&str) -> bool,
+    mut f: impl FnMut(&str, Member),
+) -> Result<()> {
+    let wanted = |name: &str| {
+        !name.ends_with('/') && FileFormat::from_path(Path::new(name)).is_some() && want(name)
+    };
+    match kind {
+        ArchiveKind::Zip => {
+            let mut zip = open_zip(archive)?;
+            for i in 0..zip.len() {
+                let entry = match zip.by_index(i) {
+                    Ok(e) => e,
+                    Err(e) => {
+                        warn!("archive {}: entry {}: {}", archive.display(), i, e);
+                        report::file_error(archive.display(), format!("entry {i}: {e}"));
+                        continue;
+                    }
+                };
+                let name = entry.name().to_string();
+                if !wanted(&name) {
+                    continue;
+                }
+                match Member::spool(entry) {
+                    Ok(m) => f(&name, m),
+                    Err(e) => member_error(archive, &name, &e),
+                }
+            }
+        }
+        ArchiveKind::Tar | ArchiveKind::TarGz => {
+            let mut tar = open_tar(archive, kind)?;
+            for entry in tar.entries()? {
+                let entry = entry?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let name = entry.path()?.to_string_lossy().to_string();
+                if !wanted(&name) {
+                    continue;
+                }
+                match Member::spool(entry) {
+                    Ok(m) => f(&name, m),
+                    Err(e) => member_error(archive, &name, &e),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn member_error(archive: &Path, name: &str, e: &anyhow::Error) {
+    warn!("archive {}: {}: {:#}", archive.display(), name, e);
+    report::file_error(format!("{}!/{name}", archive.display()), e);
+}
+
+fn open_zip(archive: &Path) -> Result<ZipArchive<BufReader<File>>> {
+    let file = File::open(archive).with_context(|| format!("open {}", archive.display()))?;
+    ZipArchive::new(BufReader::new(file)).context("open zip archive")
+}
+
+fn open_tar(archive: &Path, kind: ArchiveKind) -> Result<tar::Archive<Box<dyn Read>>> {
+    let file = File::open(archive).with_context(|| format!("open {}", archive.display()))?;
+    let rdr: Box<dyn Read> = match kind {
+        ArchiveKind::TarGz => Box::new(GzDecoder::new(BufReader::new(file))),
+        _ => Box::new(BufReader::new(file)),
+    };
+    Ok(tar::Archive::new(rdr))
+}
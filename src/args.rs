@@ -1,4 +1,6 @@
-use crate::model::Verbosity;
+use crate::filter::EntryFilter;
+use crate::model::{ExportFormat, MatchBy, OutputFormat, Verbosity};
+use crate::scan::ScanOpts;
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
@@ -18,6 +20,16 @@ pub struct Cli {
     #[arg(long = "verbose", short = 'v', value_enum, default_value_t = Verbosity::Info)]
     pub verbose: Verbosity,
 
+    /// Result on stdout: text, or one JSON object with counts and per-file
+    /// errors (logs stay on stderr) (global)
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    /// Run the command but do not save the DB; print the entries it would
+    /// add, change and remove (global)
+    #[arg(long)]
+    pub dry_run: bool,
+
     /// Command to run
     #[command(subcommand)]
     pub cmd: Commands,
@@ -25,19 +37,34 @@ pub struct Cli {
 
 #[derive(Debug, Subcommand, Clone)]
 pub enum Commands {
-    /// Scan a directory tree for .epub files and load/update the DB
+    /// Scan a directory tree for .epub files and load/update the DB.
+    /// Without DIR, rescans every named root in the DB.
     Load {
-        /// Root directory to scan (recursively) for .epub files
+        /// Root directory to scan (recursively) for .epub files, or a
+        /// webdav://, webdavs://, sftp:// or s3://bucket/prefix URI
         #[arg(value_name = "DIR")]
-        root: PathBuf,
+        root: Option<PathBuf>,
 
-        /// Follow symlinks when walking directories
-        #[arg(long)]
-        follow_symlinks: bool,
+        /// Register DIR as (or re-point) this named root and store entries relative to it.
+        /// Without DIR, rescan only this root.
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+
+        #[command(flatten)]
+        scan: ScanOpts,
 
         /// Do not compute xxhash; set xxhash field to null
         #[arg(long)]
         no_hash: bool,
+
+        /// Only compute the quick partial hash (size + first/middle/last 64 KiB);
+        /// leave xxhash null for a later `rehash`
+        #[arg(long, conflicts_with = "no_hash")]
+        quick_hash: bool,
+
+        /// Also index books inside .zip/.tar/.tar.gz bundles (without extracting them)
+        #[arg(long)]
+        descend_archives: bool,
     },
 
     /// Compute and fill missing xxhash values for entries in the current DB.
@@ -49,7 +76,37 @@ pub enum Commands {
     },
 
     /// Check DB entries against the filesystem (mark missing/changed)
-    Check,
+    Check {
+        /// Trust a matching size + quick hash instead of re-reading whole files
+        #[arg(long)]
+        quick: bool,
+    },
+
+    /// Reconcile the given directories, then keep the DB live from filesystem events
+    Watch {
+        /// Directories to watch (recursively)
+        #[arg(value_name = "DIR", required = true)]
+        roots: Vec<PathBuf>,
+
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Do not compute xxhash for new files; set xxhash field to null
+        #[arg(long)]
+        no_hash: bool,
+
+        /// Quiet time (ms) after the last event before changes are applied
+        #[arg(long, value_name = "MS", default_value_t = 2000)]
+        debounce_ms: u64,
+
+        /// Save at most this often (seconds) while there are unsaved changes
+        #[arg(long, value_name = "SECS", default_value_t = 60)]
+        save_interval: u64,
+
+        /// Save as soon as this many changes are pending
+        #[arg(long, value_name = "N", default_value_t = 100)]
+        save_after: usize,
+    },
 
     /// Remove stale entries from the DB
     Prune,
@@ -61,6 +118,123 @@ pub enum Commands {
         other: PathBuf,
     },
 
+    /// Compare two books.json files: entries only in A, only in B, and in
+    /// both with different content or fields (ignores --db)
+    Diff {
+        /// Older / reference DB
+        #[arg(value_name = "A")]
+        a: PathBuf,
+
+        /// DB to compare against A
+        #[arg(value_name = "B")]
+        b: PathBuf,
+
+        /// Pair entries by path, or by hash to follow renames and moves
+        #[arg(long, value_enum, default_value_t = MatchBy::Path)]
+        by: MatchBy,
+    },
+
+    /// Check EPUB structure (container, OPF, manifest, spine, XHTML, links) and store the result
+    Validate {
+        /// Re-validate entries that already have a result
+        #[arg(long)]
+        force: bool,
+
+        #[command(flatten)]
+        filter: EntryFilter,
+    },
+
+    /// Report groups of duplicate files (quick hash candidates, confirmed by full hash)
+    Dedupe,
+
+    /// Manage named library roots
+    Root {
+        #[command(subcommand)]
+        action: RootAction,
+    },
+
+    /// Correct an entry's title / authors / series in the DB, and optionally in the EPUB
+    Edit {
+        /// Entry id (or a unique prefix; see `export --fields id,full_path`) or file path
+        #[arg(value_name = "ID|PATH")]
+        target: String,
+
+        /// New title
+        #[arg(long)]
+        title: Option<String>,
+
+        /// Author; repeat for several (the first is the main author)
+        #[arg(long = "author", value_name = "NAME")]
+        authors: Vec<String>,
+
+        /// Series name ("" removes the book from its series)
+        #[arg(long)]
+        series: Option<String>,
+
+        /// Position within the series
+        #[arg(long, requires = "series")]
+        series_index: Option<String>,
+
+        /// Also rewrite the OPF inside the EPUB (repacks the file, rehashes the entry)
+        #[arg(long)]
+        write_file: bool,
+    },
+
+    /// Move (or copy) books into a tree named after their metadata
+    Organize {
+        /// Target path below --dest; `{field}` or `{field:0N}` placeholders
+        #[arg(
+            long,
+            default_value = "{author_sort}/{series}/{series_index:02} - {title}.{ext}"
+        )]
+        template: String,
+
+        /// Directory the template is rendered under
+        #[arg(long, value_name = "DIR")]
+        dest: PathBuf,
+
+        /// Copy instead of move (the copies are added as new entries)
+        #[arg(long)]
+        copy: bool,
+
+        #[command(flatten)]
+        filter: EntryFilter,
+    },
+
+    /// Import books from another catalog (read-only on the source)
+    Import {
+        #[command(subcommand)]
+        source: ImportSource,
+    },
+
+    /// Export (a filtered subset of) the DB as flat CSV / TSV / JSON Lines
+    Export {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+
+        /// Comma-separated columns (default: all; `other_metadata.<key>` or `other_metadata.*`)
+        #[arg(long, value_name = "FIELDS", value_delimiter = ',')]
+        fields: Vec<String>,
+
+        /// Write to FILE instead of stdout
+        #[arg(long, short = 'o', value_name = "FILE")]
+        out: Option<PathBuf>,
+
+        #[command(flatten)]
+        filter: EntryFilter,
+    },
+
+    /// Render a static, self-contained HTML catalog
+    Site {
+        /// Output directory (created if needed)
+        #[arg(long, value_name = "DIR", default_value = "catalog")]
+        out: PathBuf,
+
+        #[command(flatten)]
+        filter: EntryFilter,
+    },
+
     /// Count the number of entries in the current DB
     Count,
 
@@ -77,3 +251,72 @@ pub enum Commands {
     /// Create a zpaq archive of epubs (DNI / stub)
     Stow,
 }
+
+impl Commands {
+    /// Subcommand name, as typed.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Commands::Load { .. } => "load",
+            Commands::Rehash { .. } => "rehash",
+            Commands::Check { .. } => "check",
+            Commands::Watch { .. } => "watch",
+            Commands::Prune => "prune",
+            Commands::Merge { .. } => "merge",
+            Commands::Diff { .. } => "diff",
+            Commands::Validate { .. } => "validate",
+            Commands::Dedupe => "dedupe",
+            Commands::Root { .. } => "root",
+            Commands::Edit { .. } => "edit",
+            Commands::Organize { .. } => "organize",
+            Commands::Import { .. } => "import",
+            Commands::Export { .. } => "export",
+            Commands::Site { .. } => "site",
+            Commands::Count => "count",
+            Commands::Serve { .. } => "serve",
+            Commands::Query => "query",
+            Commands::Stow => "stow",
+        }
+    }
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum RootAction {
+    /// List named roots with their directory and entry count
+    List,
+
+    /// Add a named root, or point an existing one at a new mountpoint
+    Set {
+        #[arg(value_name = "NAME")]
+        name: String,
+        #[arg(value_name = "DIR")]
+        dir: PathBuf,
+    },
+
+    /// Forget a named root; its entries keep their last absolute path
+    Remove {
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum ImportSource {
+    /// Calibre library: metadata.db plus each book's metadata.opf
+    Calibre {
+        /// Calibre library directory (the one containing metadata.db)
+        #[arg(value_name = "LIBRARY_DIR")]
+        library: PathBuf,
+
+        /// Also register the library directory as this named root
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+
+        /// Skip hashing (fast; fill later with `rehash`)
+        #[arg(long)]
+        no_hash: bool,
+
+        /// Record only the quick hash (see `load --quick-hash`)
+        #[arg(long, conflicts_with = "no_hash")]
+        quick_hash: bool,
+    },
+}
@@ -0,0 +1,425 @@
+//! Bibliography rendering (BibTeX, CSL-JSON, RIS) for catalog entries.
+//!
+//! People come from `author` or `other_metadata["creators"]` plus
+//! `other_metadata["contributors"]` ("Name (role)" items joined by "; ",
+//! roles are MARC relators: edt = editor, trl = translator). ISBN/DOI are
+//! parsed from `other_metadata["identifier"]`.
+
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+use crate::model::BookEntry;
+
+/// A person split for citation formats; `given` is empty for single-word or
+/// corporate names.
+#[derive(Debug, Clone)]
+pub struct Name {
+    pub family: String,
+    pub given: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Citation {
+    pub key: String,
+    pub title: Option<String>,
+    pub authors: Vec<Name>,
+    pub editors: Vec<Name>,
+    pub translators: Vec<Name>,
+    pub contributors: Vec<Name>,
+    pub publisher: Option<String>,
+    /// (year, month, day) from `publish_date`
+    pub date: Option<(i32, Option<u32>, Option<u32>)>,
+    pub isbn: Option<String>,
+    pub doi: Option<String>,
+    pub language: Option<String>,
+    pub path: String,
+}
+
+/// Build citations for `entries` with stable keys: `<family><year><first
+/// title word>`, lowercase ASCII. A key that more than one live entry of the
+/// whole DB (`all`) would get is suffixed with the start of the entry's id,
+/// so a book keeps its key whatever the selection or order.
+pub fn citations(all: &[BookEntry], entries: &[&BookEntry]) -> Vec<Citation> {
+    let mut seen: BTreeMap<String, usize> = BTreeMap::new();
+    for e in all.iter().filter(|e| !e.stale) {
+        *seen.entry(citation(e).key).or_default() += 1;
+    }
+    entries
+        .iter()
+        .map(|e| {
+            let mut c = citation(e);
+            if seen.get(&c.key).is_some_and(|&n| n > 1) {
+                c.key = format!("{}-{}", c.key, &e.id()[..8]);
+            }
+            c
+        })
+        .collect()
+}
+
+fn citation(e: &BookEntry) -> Citation {
+    let mut authors = Vec::new();
+    let mut editors = Vec::new();
+    let mut translators = Vec::new();
+    let mut contributors = Vec::new();
+
+    let creators = e
+        .other_metadata
+        .get("creators")
+        .cloned()
+        .or_else(|| e.author.clone())
+        .unwrap_or_default();
+    let people = split_people(&creators).map(|p| (p, "aut")).chain(
+        split_people(e.other_metadata.get("contributors").map_or("", |s| s)).map(|p| (p, "ctb")),
+    );
+    for ((name, role), default_role) in people {
+        let bucket = match role.unwrap_or(default_role) {
+            "aut" => &mut authors,
+            "edt" => &mut editors,
+            "trl" => &mut translators,
+            _ => &mut contributors,
+        };
+        bucket.push(split_name(name));
+    }
+
+    let date = e.publish_date.as_deref().and_then(parse_date);
+    let (isbn, doi) = e
+        .other_metadata
+        .get("identifier")
+        .map(|id| parse_identifier(id))
+        .unwrap_or_default();
+
+    let key = {
+        let family = authors.first().map_or("anon", |n| n.family.as_str());
+        let year = date.map(|d| d.0.to_string()).unwrap_or_default();
+        let word = e
+            .title
+            .as_deref()
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(ascii_key)
+            .find(|w| !w.is_empty() && !is_stopword(w))
+            .unwrap_or_default();
+        let k = format!("{}{}{}", ascii_key(family), year, word);
+        if k.is_empty() { "untitled".into() } else { k }
+    };
+
+    Citation {
+        key,
+        title: e.title.clone(),
+        authors,
+        editors,
+        translators,
+        contributors,
+        publisher: e.publisher.clone(),
+        date,
+        isbn,
+        doi,
+        language: e.other_metadata.get("language").cloned(),
+        path: e.full_path.clone(),
+    }
+}
+
+/// Author names of an entry as written in the OPF ("Jane Doe"), without
+/// editors/translators/contributors.
+pub fn author_names(e: &BookEntry) -> Vec<String> {
+    let creators = e
+        .other_metadata
+        .get("creators")
+        .cloned()
+        .or_else(|| e.author.clone())
+        .unwrap_or_default();
+    split_people(&creators)
+        .filter(|(_, role)| role.is_none_or(|r| r == "aut"))
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+/// "Jane Doe" → "Doe, Jane" (library sort form; particles stay with the family name).
+pub fn sort_name(name: &str) -> String {
+    let n = split_name(name);
+    if n.given.is_empty() {
+        n.family
+    } else {
+        format!("{}, {}", n.family, n.given)
+    }
+}
+
+/// "A (aut); B (trl); C" → (name, Some(role)) items.
+fn split_people(s: &str) -> impl Iterator<Item = (&str, Option<&str>)> {
+    s.split("; ")
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(
+            |p| match p.strip_suffix(')').and_then(|q| q.rsplit_once(" (")) {
+                Some((name, role)) => (name.trim(), Some(role.trim())),
+                None => (p, None),
+            },
+        )
+}
+
+/// "Doe, Jane" or "Jane Doe" → family "Doe", given "Jane".
+/// Name particles stay with the family name ("Ursula K. Le Guin" → "Le Guin").
+fn split_name(s: &str) -> Name {
+    if let Some((family, given)) = s.split_once(',') {
+        return Name {
+            family: family.trim().into(),
+            given: given.trim().into(),
+        };
+    }
+    let words: Vec<&str> = s.split_whitespace().collect();
+    let mut cut = words.len().saturating_sub(1);
+    while cut > 1 && is_particle(words[cut - 1]) {
+        cut -= 1;
+    }
+    Name {
+        family: words[cut..].join(" "),
+        given: words[..cut].join(" "),
+    }
+}
+
+fn is_particle(w: &str) -> bool {
+    matches!(
+        w.to_ascii_lowercase().as_str(),
+        "de" | "del"
+            | "della"
+            | "der"
+            | "di"
+            | "da"
+            | "du"
+            | "la"
+            | "le"
+            | "van"
+            | "von"
+            | "ten"
+            | "ter"
+    )
+}
+
+/// Leading YYYY[-MM[-DD]] of an OPF date.
+fn parse_date(s: &str) -> Option<(i32, Option<u32>, Option<u32>)> {
+    let mut parts = s.trim().split(['-', 'T']);
+    let year = parts.next()?.get(..4)?.parse().ok()?;
+    let month = parts
+        .next()
+        .and_then(|m| m.parse().ok())
+        .filter(|m| (1..=12).contains(m));
+    let day = month
+        .and(parts.next())
+        .and_then(|d| d.parse().ok())
+        .filter(|d| (1..=31).contains(d));
+    Some((year, month, day))
+}
+
+/// ISBN-10/13 or DOI from identifiers like `urn:isbn:978…`, `ISBN 0-19-…`,
+/// `doi:10.…`, `https://doi.org/10.…`.
+fn parse_identifier(id: &str) -> (Option<String>, Option<String>) {
+    let lower = id.trim().to_ascii_lowercase();
+    for prefix in ["https://doi.org/", "http://dx.doi.org/", "urn:doi:", "doi:"] {
+        if lower.starts_with(prefix) {
+            let doi = id.trim()[prefix.len()..].trim();
+            return (None, (!doi.is_empty()).then(|| doi.to_string()));
+        }
+    }
+    if lower.starts_with("10.") && lower.contains('/') {
+        return (None, Some(id.trim().to_string()));
+    }
+    let digits: String = lower
+        .trim_start_matches("urn:")
+        .trim_start_matches("isbn")
+        .trim_start_matches(':')
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' '))
+        .collect();
+    // Byte lengths and slices below: anything non-ASCII is not an ISBN
+    let is_isbn = digits.is_ascii()
+        && ((digits.len() == 13 && digits.chars().all(|c| c.is_ascii_digit()))
+            || (digits.len() == 10
+                && digits[..9].chars().all(|c| c.is_ascii_digit())
+                && digits[9..].chars().all(|c| c.is_ascii_digit() || c == 'x')));
+    (is_isbn.then(|| digits.to_ascii_uppercase()), None)
+}
+
+fn ascii_key(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn is_stopword(w: &str) -> bool {
+    matches!(w, "a" | "an" | "the" | "of" | "on" | "in" | "and")
+}
+
+fn display_name(n: &Name) -> String {
+    if n.given.is_empty() {
+        n.family.clone()
+    } else {
+        format!("{}, {}", n.family, n.given)
+    }
+}
+
+/// BibTeX `@book` records (biblatex-compatible fields).
+pub fn to_bibtex(cites: &[Citation]) -> String {
+    let mut out = String::new();
+    for c in cites {
+        let mut fields: Vec<(&str, String)> = Vec::new();
+        let names = |v: &[Name]| v.iter().map(display_name).collect::<Vec<_>>().join(" and ");
+        if !c.authors.is_empty() {
+            fields.push(("author", names(&c.authors)));
+        }
+        if !c.editors.is_empty() {
+            fields.push(("editor", names(&c.editors)));
+        }
+        if !c.translators.is_empty() {
+            fields.push(("translator", names(&c.translators)));
+        }
+        if let Some(t) = &c.title {
+            fields.push(("title", t.clone()));
+        }
+        if let Some(p) = &c.publisher {
+            fields.push(("publisher", p.clone()));
+        }
+        if let Some((y, m, d)) = c.date {
+            fields.push(("year", y.to_string()));
+            fields.push(("date", iso_date(y, m, d)));
+        }
+        if let Some(i) = &c.isbn {
+            fields.push(("isbn", i.clone()));
+        }
+        if let Some(d) = &c.doi {
+            fields.push(("doi", d.clone()));
+        }
+        if let Some(l) = &c.language {
+            fields.push(("language", l.clone()));
+        }
+        fields.push(("file", c.path.clone()));
+
+        out.push_str(&format!("@book{{{},\n", c.key));
+        for (k, v) in fields {
+            out.push_str(&format!("  {k} = {{{}}},\n", bibtex_escape(&v)));
+        }
+        out.push_str("}\n\n");
+    }
+    out
+}
+
+fn bibtex_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                out.push('\\');
+                out.push(ch);
+            }
+            '~' => out.push_str("\\textasciitilde{}"),
+            '^' => out.push_str("\\textasciicircum{}"),
+            '\\' => out.push_str("\\textbackslash{}"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+fn iso_date(y: i32, m: Option<u32>, d: Option<u32>) -> String {
+    match (m, d) {
+        (Some(m), Some(d)) => format!("{y:04}-{m:02}-{d:02}"),
+        (Some(m), None) => format!("{y:04}-{m:02}"),
+        _ => format!("{y:04}"),
+    }
+}
+
+/// CSL-JSON array (citeproc, Zotero, pandoc).
+pub fn to_csl_json(cites: &[Citation]) -> Value {
+    let names = |v: &[Name]| -> Value {
+        v.iter()
+            .map(|n| {
+                if n.given.is_empty() {
+                    json!({ "literal": n.family })
+                } else {
+                    json!({ "family": n.family, "given": n.given })
+                }
+            })
+            .collect()
+    };
+    cites
+        .iter()
+        .map(|c| {
+            let mut o = serde_json::Map::new();
+            o.insert("id".into(), json!(c.key));
+            o.insert("type".into(), json!("book"));
+            let mut put = |k: &str, v: Option<Value>| {
+                if let Some(v) = v {
+                    o.insert(k.into(), v);
+                }
+            };
+            put("title", c.title.clone().map(Value::from));
+            put("author", (!c.authors.is_empty()).then(|| names(&c.authors)));
+            put("editor", (!c.editors.is_empty()).then(|| names(&c.editors)));
+            put(
+                "translator",
+                (!c.translators.is_empty()).then(|| names(&c.translators)),
+            );
+            put(
+                "contributor",
+                (!c.contributors.is_empty()).then(|| names(&c.contributors)),
+            );
+            put("publisher", c.publisher.clone().map(Value::from));
+            put(
+                "issued",
+                c.date.map(|(y, m, d)| {
+                    let parts: Vec<Value> = [Some(y as u32), m, d]
+                        .into_iter()
+                        .map_while(|p| p.map(Value::from))
+                        .collect();
+                    json!({ "date-parts": [parts] })
+                }),
+            );
+            put("ISBN", c.isbn.clone().map(Value::from));
+            put("DOI", c.doi.clone().map(Value::from));
+            put("language", c.language.clone().map(Value::from));
+            Value::Object(o)
+        })
+        .collect()
+}
+
+/// RIS records (`TY  - BOOK` … `ER  - `).
+pub fn to_ris(cites: &[Citation]) -> String {
+    let mut out = String::new();
+    for c in cites {
+        let mut line = |tag: &str, v: &str| out.push_str(&format!("{tag}  - {v}\r\n"));
+        line("TY", "BOOK");
+        line("ID", &c.key);
+        if let Some(t) = &c.title {
+            line("TI", t);
+        }
+        for n in &c.authors {
+            line("AU", &display_name(n));
+        }
+        for n in &c.editors {
+            line("A2", &display_name(n));
+        }
+        for n in c.translators.iter().chain(&c.contributors) {
+            line("A4", &display_name(n));
+        }
+        if let Some(p) = &c.publisher {
+            line("PB", p);
+        }
+        if let Some((y, m, d)) = c.date {
+            line("PY", &y.to_string());
+            let md = |v: Option<u32>| v.map(|v| format!("{v:02}")).unwrap_or_default();
+            line("DA", &format!("{y:04}/{}/{}/", md(m), md(d)));
+        }
+        if let Some(i) = &c.isbn {
+            line("SN", i);
+        }
+        if let Some(d) = &c.doi {
+            line("DO", d);
+        }
+        if let Some(l) = &c.language {
+            line("LA", l);
+        }
+        line("L1", &c.path);
+        line("ER", "");
+    }
+    out
+}
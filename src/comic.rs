@@ -0,0 +1,154 @@
+//! Comic book archives (`.cbz`, `.cbr`, `.cb7`):
+//! - CBZ: `ComicInfo.xml` (series, number, writer, penciller, publisher,
+//!   year/month/day, summary), page count, cover = first image in natural order
+//! - CBR (RAR) and CB7 (7-Zip) are indexed by path and hash only
+
+use anyhow::{Context, Result};
+use roxmltree::Document;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::io::{Read, Seek};
+use std::path::Path;
+use zip::ZipArchive;
+
+use crate::model::EpubMeta;
+
+const IMAGE_EXTS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "bmp", "avif", "jxl"];
+
+fn is_cbz(p: &Path) -> bool {
+    p.extension().is_some_and(|e| e.eq_ignore_ascii_case("cbz"))
+}
+
+pub fn read_comic_metadata<R: Read + Seek>(r: R, name: &Path) -> Result<EpubMeta> {
+    if !is_cbz(name) {
+        return Ok(EpubMeta::default());
+    }
+    let mut zip = ZipArchive::new(r).context("open zip archive")?;
+    let mut meta = EpubMeta::default();
+    let mut other = BTreeMap::new();
+    other.insert("pages".into(), pages(&zip).len().to_string());
+
+    let info = zip
+        .file_names()
+        .find(|n| n.rsplit('/').next() == Some("ComicInfo.xml"))
+        .map(str::to_string);
+    if let Some(name) = info {
+        let mut xml = String::new();
+        zip.by_name(&name)?.read_to_string(&mut xml)?;
+        let doc = Document::parse(&xml).context("parse ComicInfo.xml")?;
+        let get = |tag: &str| {
+            doc.root_element()
+                .children()
+                .find(|n| n.has_tag_name(tag))
+                .and_then(|n| n.text())
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+        };
+
+        // Writer/Penciller are comma-separated lists
+        let writers: Vec<String> = get("Writer")
+            .map(|w| w.split(',').map(|s| s.trim().to_string()).collect())
+            .unwrap_or_default();
+        meta.author = writers.first().cloned();
+        if writers.len() > 1 {
+            other.insert("creators".into(), writers.join("; "));
+        }
+        if let Some(p) = get("Penciller") {
+            other.insert("penciller".into(), p.replace(", ", "; "));
+        }
+        if let Some(s) = get("Series") {
+            other.insert("series".into(), s);
+        }
+        if let Some(n) = get("Number") {
+            other.insert("series_index".into(), n);
+        }
+        if let Some(l) = get("LanguageISO") {
+            other.insert("language".into(), l);
+        }
+        meta.title = get("Title");
+        meta.publisher = get("Publisher");
+        meta.description = get("Summary");
+        meta.publish_date = get("Year").map(|y| match (get("Month"), get("Day")) {
+            (Some(m), Some(d)) => format!("{y}-{m:0>2}-{d:0>2}"),
+            (Some(m), None) => format!("{y}-{m:0>2}"),
+            _ => y,
+        });
+    }
+    meta.other_metadata = other;
+    Ok(meta)
+}
+
+/// First page image (natural order) and its media type.
+pub fn read_comic_cover<R: Read + Seek>(r: R, name: &Path) -> Result<Option<(Vec<u8>, String)>> {
+    if !is_cbz(name) {
+        return Ok(None);
+    }
+    let mut zip = ZipArchive::new(r).context("open zip archive")?;
+    let Some(first) = pages(&zip).into_iter().next() else {
+        return Ok(None);
+    };
+    let mut bytes = Vec::new();
+    zip.by_name(&first)?.read_to_end(&mut bytes)?;
+    let ext = first
+        .rsplit('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let media_type = match ext.as_str() {
+        "jpg" | "jpeg" => "image/jpeg".to_string(),
+        other => format!("image/{other}"),
+    };
+    Ok(Some((bytes, media_type)))
+}
+
+/// Image entries in natural order (`page2` before `page10`), skipping
+/// macOS resource forks.
+fn pages<R: Read + Seek>(zip: &ZipArchive<R>) -> Vec<String> {
+    let mut pages: Vec<String> = zip
+        .file_names()
+        .filter(|n| !n.contains("__MACOSX/") && !n.ends_with('/'))
+        .filter(|n| {
+            n.rsplit_once('.')
+                .is_some_and(|(_, ext)| IMAGE_EXTS.contains(&ext.to_ascii_lowercase().as_str()))
+        })
+        .map(str::to_string)
+        .collect();
+    pages.sort_by(|a, b| natural_cmp(a, b));
+    pages
+}
+
+/// Compare with digit runs as numbers, case-insensitively otherwise.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let num = |it: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut s = String::new();
+                    while let Some(c) = it.next_if(char::is_ascii_digit) {
+                        s.push(c);
+                    }
+                    s
+                };
+                let (x, y) = (num(&mut a), num(&mut b));
+                let (xt, yt) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ord = xt.len().cmp(&yt.len()).then_with(|| xt.cmp(yt));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ord = x.to_lowercase().cmp(y.to_lowercase());
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
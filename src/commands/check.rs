@@ -1,63 +1,339 @@
 use anyhow::Result;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
-use tracing::{info, warn};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
 
+use crate::archive::{self, ArchiveKind, Members};
+use crate::container;
 use crate::hash;
 use crate::metadata;
-use crate::model::{BookEntry, BooksDb, FileFormat};
-use crate::util::{file_uri, now_iso8601};
+use crate::model::{BookEntry, BooksDb};
+use crate::remote::{self, RangeReader, RemoteUri};
+use crate::report;
+use crate::util::{absolute, file_uri, now_iso8601};
+use crate::volume::Volumes;
 
-pub fn cmd_check(db: &mut BooksDb) -> Result<()> {
-    check_and_update(db)
+/// (size, quick hash) pairs that more than one live entry shares.
+pub type Collisions = HashSet<(u64, u128)>;
+
+/// Verify non-stale entries against the filesystem.
+/// With `quick`, an entry whose size and quick hash still match is taken as
+/// unchanged without reading the whole file; the full hash is only computed
+/// when the quick hash is absent or differs, or when another entry has the
+/// same size and quick hash (a collision the quick hash cannot settle).
+/// Without `quick` no quick hash is computed.
+/// Entries whose file is gone because their volume is not mounted are
+/// reported as "volume offline" and left untouched instead of marked missing.
+pub fn cmd_check(db: &mut BooksDb, quick: bool) -> Result<()> {
+    check_and_update(db, quick, |_| true)
+}
+
+/// `cmd_check` limited to local entries (bundle members included) below one
+/// of `roots`, which must be absolute; `watch` reconciles its roots with it.
+pub fn check_roots(db: &mut BooksDb, quick: bool, roots: &[PathBuf]) -> Result<()> {
+    check_and_update(db, quick, |e| {
+        let p = absolute(&archive::disk_path(e));
+        !remote::is_remote(e) && roots.iter().any(|r| p.starts_with(r))
+    })
 }
 
-fn check_and_update(db: &mut BooksDb) -> Result<()> {
+fn check_and_update(
+    db: &mut BooksDb,
+    quick: bool,
+    scope: impl Fn(&BookEntry) -> bool,
+) -> Result<()> {
     let mut to_push = Vec::new();
+    let mut vols = Volumes::new();
+    let mut offline: BTreeMap<String, usize> = BTreeMap::new();
+    let (mut checked, mut newly_missing) = (0usize, 0usize);
+    let members = Members::new(db.books.iter().filter(|e| !e.stale && scope(e)));
+    let collisions = quick.then(|| collisions(&db.books));
 
-    for existing in db.books.iter_mut().filter(|e| !e.stale) {
+    for existing in db.books.iter_mut().filter(|e| !e.stale && scope(e)) {
         let path = PathBuf::from(&existing.full_path);
-        if let Ok(md) = fs::metadata(&path) {
-            let size = md.len();
-            let new_hash = hash::xxh3_file(&path).ok();
-            if existing.xxhash != new_hash {
-                let meta = match existing.format {
-                    FileFormat::Epub => metadata::extract_epub_metadata(&path).unwrap_or_default(),
-                    FileFormat::Pdf => metadata::extract_pdf_metadata(&path).unwrap_or_default(),
-                };
-                let fresh = BookEntry {
-                    full_path: existing.full_path.clone(),
-                    uri_path: file_uri(&path),
-                    protocol: existing.protocol.clone(),
-                    filename: existing.filename.clone(),
-                    xxhash: new_hash,
-                    date_found: now_iso8601(),
-                    missing: false,
-                    stale: false,
-                    size_bytes: size,
-                    format: existing.format,
-                    title: meta.title,
-                    author: meta.author,
-                    description: meta.description,
-                    chapters: meta.chapters,
-                    publish_date: meta.publish_date,
-                    publisher: meta.publisher,
-                    other_metadata: meta.other_metadata,
-                };
-                existing.stale = true;
-                existing.missing = false;
-                to_push.push(fresh);
-                info!("Changed → new record: {}", existing.full_path);
-            } else {
-                // update size if needed
-                existing.size_bytes = size;
+        if !archive::disk_path(existing).exists() && vols.is_offline(existing) {
+            let vol = existing.volume.clone().unwrap_or_default();
+            *offline.entry(vol).or_default() += 1;
+            debug!("Volume offline, skipped: {}", existing.full_path);
+            continue;
+        }
+
+        checked += 1;
+        let was_missing = existing.missing;
+        // Remote files have no local volume
+        let local = !remote::is_remote(existing);
+        if let Some(mut fresh) = check_entry(existing, collisions.as_ref(), &members) {
+            if local {
+                fresh.volume = vols.volume_of(&path);
             }
-        } else {
-            existing.missing = true;
-            warn!("Unreadable, marked missing: {}", existing.full_path);
+            to_push.push(fresh);
+        }
+        if existing.missing {
+            newly_missing += usize::from(!was_missing);
+        } else if local {
+            existing.volume = vols.volume_of(&path);
         }
     }
 
+    for (vol, n) in &offline {
+        warn!("Volume offline: {} ({} entr(y/ies) left untouched)", vol, n);
+    }
+    info!(
+        "check summary → checked: {}; changed: {}; newly missing: {}; volume offline: {}",
+        checked,
+        to_push.len(),
+        newly_missing,
+        offline.values().sum::<usize>()
+    );
+
     db.books.extend(to_push);
     Ok(())
 }
+
+fn collisions(books: &[BookEntry]) -> Collisions {
+    let mut seen: HashMap<(u64, u128), usize> = HashMap::new();
+    for e in books.iter().filter(|e| !e.stale) {
+        if let Some(q) = e.quick_hash {
+            *seen.entry((e.size_bytes, q)).or_default() += 1;
+        }
+    }
+    seen.into_iter()
+        .filter(|(_, n)| *n > 1)
+        .map(|(k, _)| k)
+        .collect()
+}
+
+/// Whether a quick-hash match can be trusted: `quick` was asked for and no
+/// other entry shares the (size, quick hash) pair.
+fn quick_match(quick: Option<&Collisions>, size: u64, q: Option<u128>) -> bool {
+    match (quick, q) {
+        (Some(collisions), Some(q)) => !collisions.contains(&(size, q)),
+        _ => false,
+    }
+}
+
+/// Check one non-stale entry against the filesystem and update it in place.
+/// Returns the fresh record to append when the content changed (the existing
+/// one is then marked stale). Shared by `check` and `watch`; bundle members
+/// are read through `members`. `quick` carries the collisions of
+/// `check --quick`; `None` always compares full hashes.
+pub fn check_entry(
+    existing: &mut BookEntry,
+    quick: Option<&Collisions>,
+    members: &Members,
+) -> Option<BookEntry> {
+    if archive::is_member(existing) {
+        return check_member(existing, members);
+    }
+    if remote::is_remote(existing) {
+        return check_remote(existing, quick);
+    }
+    let path = PathBuf::from(&existing.full_path);
+    if fs::metadata(&path).is_ok() {
+        let size = container::book_size(&path);
+        let new_quick = quick.and_then(|_| hash::quick_hash(&path).ok());
+        if existing.quick_hash == new_quick && existing.size_bytes == size {
+            if quick_match(quick, size, new_quick) {
+                debug!("Unchanged (quick hash): {}", existing.full_path);
+                return None;
+            }
+            if new_quick.is_some() {
+                debug!("Quick hash shared, full hash: {}", existing.full_path);
+            }
+        }
+        let new_hash = hash::xxh3_file(&path).ok();
+        let new_content = if existing.xxhash != new_hash {
+            hash::content_hash(&path, existing.format)
+        } else {
+            None
+        };
+        if existing.xxhash != new_hash
+            && new_content.is_some()
+            && existing.content_hash == new_content
+        {
+            // Re-zipped but identical inside: same book, refresh in place.
+            existing.xxhash = new_hash;
+            existing.quick_hash = new_quick;
+            existing.size_bytes = size;
+            existing.missing = false;
+            existing.clear_validation();
+            info!("Repackaged (same content): {}", existing.full_path);
+        } else if existing.xxhash != new_hash {
+            let meta = metadata::extract_metadata(&path, existing.format).unwrap_or_default();
+            let fresh = BookEntry {
+                full_path: existing.full_path.clone(),
+                root: existing.root.clone(),
+                rel_path: existing.rel_path.clone(),
+                volume: existing.volume.clone(),
+                uri_path: file_uri(&path),
+                protocol: existing.protocol.clone(),
+                filename: existing.filename.clone(),
+                xxhash: new_hash,
+                content_hash: new_content,
+                quick_hash: new_quick,
+                etag: None,
+                date_found: now_iso8601(),
+                missing: false,
+                stale: false,
+                size_bytes: size,
+                format: existing.format,
+                validation: None,
+                validation_issues: Vec::new(),
+                drm: meta.drm,
+                title: meta.title,
+                author: meta.author,
+                description: meta.description,
+                chapters: meta.chapters,
+                publish_date: meta.publish_date,
+                publisher: meta.publisher,
+                other_metadata: meta.other_metadata,
+            };
+            existing.stale = true;
+            existing.missing = false;
+            info!("Changed → new record: {}", existing.full_path);
+            return Some(fresh);
+        } else {
+            // update size (and backfill quick hash) if needed
+            existing.size_bytes = size;
+            if new_quick.is_some() {
+                existing.quick_hash = new_quick;
+            }
+        }
+    } else {
+        existing.missing = true;
+        warn!("Unreadable, marked missing: {}", existing.full_path);
+    }
+    None
+}
+
+/// `check_entry` for a book inside a bundle: the member is re-read from the
+/// archive; a vanished archive or member marks the entry missing.
+fn check_member(existing: &mut BookEntry, members: &Members) -> Option<BookEntry> {
+    let m = match members.read(existing) {
+        Ok(m) => m,
+        Err(e) => {
+            existing.missing = true;
+            warn!(
+                "Unreadable, marked missing: {} ({:#})",
+                existing.full_path, e
+            );
+            report::file_error(&existing.full_path, &e);
+            return None;
+        }
+    };
+    let size = m.size;
+    // The member was read whole to spool it, so every hash is cheap and the
+    // full hash decides; an entry indexed with the quick hash only gets it
+    let (new_hash, new_quick, new_content) = m.hashes(existing.format, false, false);
+    existing.missing = false;
+    let quick_only = existing.xxhash.is_none()
+        && existing.quick_hash == new_quick
+        && existing.size_bytes == size;
+    if existing.xxhash == new_hash || quick_only {
+        existing.xxhash = new_hash;
+        existing.size_bytes = size;
+        existing.quick_hash = new_quick;
+        debug!("Unchanged: {}", existing.full_path);
+        return None;
+    }
+    if new_content.is_some() && existing.content_hash == new_content {
+        // Re-zipped but identical inside: same book, refresh in place.
+        existing.xxhash = new_hash;
+        existing.quick_hash = new_quick;
+        existing.size_bytes = size;
+        existing.clear_validation();
+        info!("Repackaged (same content): {}", existing.full_path);
+        return None;
+    }
+    let (bundle, member) = archive::split(existing)?;
+    let bundle = Path::new(bundle);
+    let kind = ArchiveKind::of(bundle)?;
+    let mut fresh = archive::member_entry(bundle, kind, member, &m, false, false);
+    fresh.full_path = existing.full_path.clone();
+    fresh.uri_path = existing.uri_path.clone();
+    fresh.root = existing.root.clone();
+    fresh.rel_path = existing.rel_path.clone();
+    fresh.volume = existing.volume.clone();
+    existing.stale = true;
+    info!("Changed → new record: {}", existing.full_path);
+    Some(fresh)
+}
+
+/// `check_entry` for a remote file: unchanged size + ETag (or, with `quick`,
+/// size + quick hash from ranged reads, unless another entry shares them) is
+/// trusted without a download.
+/// An unreachable server leaves the entry untouched; a file the server no
+/// longer has is marked missing.
+fn check_remote(existing: &mut BookEntry, quick: Option<&Collisions>) -> Option<BookEntry> {
+    let stat = remote::locate(existing).and_then(|(src, path)| {
+        let file = src.stat(&path)?;
+        Ok((src, file))
+    });
+    let (src, file) = match stat {
+        Ok((src, Some(file))) => (src, file),
+        Ok((_, None)) => {
+            existing.missing = true;
+            warn!("Gone from server, marked missing: {}", existing.full_path);
+            return None;
+        }
+        Err(e) => {
+            warn!(
+                "Unreachable, left untouched: {} ({:#})",
+                existing.full_path, e
+            );
+            report::file_error(&existing.full_path, &e);
+            return None;
+        }
+    };
+    existing.missing = false;
+    if file.etag.is_some() && file.etag == existing.etag && file.size == existing.size_bytes {
+        debug!("Unchanged (ETag): {}", existing.full_path);
+        return None;
+    }
+    if quick_match(quick, file.size, existing.quick_hash) && file.size == existing.size_bytes {
+        let reader = RangeReader::new(&src, &file.path, file.size);
+        if hash::quick_hash_reader(reader, file.size).ok() == existing.quick_hash {
+            existing.etag = file.etag;
+            debug!("Unchanged (quick hash): {}", existing.full_path);
+            return None;
+        }
+    }
+
+    let u = match RemoteUri::parse(&existing.full_path) {
+        Ok(u) => u,
+        Err(e) => {
+            warn!(
+                "Bad remote path, left untouched: {} ({:#})",
+                existing.full_path, e
+            );
+            report::file_error(&existing.full_path, &e);
+            return None;
+        }
+    };
+    let fresh = remote::build_entry(&u, &src, &file, false, false);
+    if fresh.xxhash.is_none() {
+        // The download failed; `build_entry` has logged and recorded it
+        debug!("Not fetched, left untouched: {}", existing.full_path);
+        return None;
+    }
+    if existing.xxhash == fresh.xxhash {
+        // Touched but identical: only the ETag moved
+        existing.etag = fresh.etag;
+        existing.quick_hash = fresh.quick_hash;
+        debug!("Unchanged: {}", existing.full_path);
+        return None;
+    }
+    if fresh.content_hash.is_some() && existing.content_hash == fresh.content_hash {
+        existing.xxhash = fresh.xxhash;
+        existing.quick_hash = fresh.quick_hash;
+        existing.size_bytes = fresh.size_bytes;
+        existing.etag = fresh.etag;
+        existing.clear_validation();
+        info!("Repackaged (same content): {}", existing.full_path);
+        return None;
+    }
+    existing.stale = true;
+    info!("Changed → new record: {}", existing.full_path);
+    Some(fresh)
+}
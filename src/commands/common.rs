@@ -1,8 +1,14 @@
 use crate::model::{BookEntry, BooksDb};
+use crate::roots;
+use crate::util::absolute;
+use anyhow::{Result, bail};
+use std::path::Path;
 use tracing::{debug, info};
 
 /// Merge logic used by `load` and `merge`:
 /// - If an entry with the same full_path exists and hash unchanged → no-op
+/// - If either side lacks a full hash but quick_hash + size match → no-op
+/// - If same path, hash changed but content_hash equal (re-zipped) → refresh hash in place
 /// - If same path but hash changed → mark old stale+missing, push new
 /// - If path not present → insert new
 pub fn merge_entry(db: &mut BooksDb, new: &mut BookEntry) {
@@ -11,10 +17,42 @@ pub fn merge_entry(db: &mut BooksDb, new: &mut BookEntry) {
         .iter_mut()
         .find(|b| b.full_path == new.full_path && !b.stale)
     {
+        // The server's current ETag is what the next `check` compares against
+        if new.etag.is_some() {
+            existing.etag = new.etag.clone();
+        }
+        // An entry indexed before its root was named joins the root now
+        roots::assign(&db.roots, existing);
         match (&existing.xxhash, &new.xxhash) {
-            (Some(old), Some(neu)) if old == neu => {
+            (Some(_), Some(_)) if same_content(existing, new) => {
+                // Backfill hashes an earlier (older or quick) scan did not record.
+                if existing.content_hash.is_none() {
+                    existing.content_hash = new.content_hash;
+                }
+                if existing.quick_hash.is_none() {
+                    existing.quick_hash = new.quick_hash;
+                }
+                backfill_drm(existing, new);
                 debug!("Unchanged: {}", new.full_path);
             }
+            (None, _) | (_, None) if same_content(existing, new) => {
+                // Quick-hash-only scan: accept the candidate match, keep any full hash.
+                if existing.xxhash.is_none() {
+                    existing.xxhash = new.xxhash;
+                }
+                backfill_drm(existing, new);
+                debug!("Unchanged (quick hash): {}", new.full_path);
+            }
+            _ if existing.content_hash.is_some() && existing.content_hash == new.content_hash => {
+                // New bytes: every hash of the old zip is out of date
+                existing.xxhash = new.xxhash;
+                existing.quick_hash = new.quick_hash;
+                existing.size_bytes = new.size_bytes;
+                existing.etag = new.etag.clone();
+                backfill_drm(existing, new);
+                existing.clear_validation();
+                info!("Repackaged (same content): {}", new.full_path);
+            }
             _ => {
                 existing.stale = true;
                 existing.missing = true;
@@ -27,3 +65,65 @@ pub fn merge_entry(db: &mut BooksDb, new: &mut BookEntry) {
         debug!("Inserted: {}", new.full_path); // debug per your preference
     }
 }
+
+/// Whether two records of one path hold the same file: equal full hashes,
+/// or (when either lacks one) equal quick hash and size. The first two arms
+/// of `merge_entry`; `diff` compares by it too.
+pub fn same_content(a: &BookEntry, b: &BookEntry) -> bool {
+    match (a.xxhash, b.xxhash) {
+        (Some(x), Some(y)) => x == y,
+        _ => a.quick_hash.is_some() && a.quick_hash == b.quick_hash && a.size_bytes == b.size_bytes,
+    }
+}
+
+/// DRM detection came after many libraries were indexed: take the scheme and
+/// the font-obfuscation note from a rescan of the same content when missing.
+fn backfill_drm(existing: &mut BookEntry, new: &BookEntry) {
+    if existing.drm.is_none() {
+        existing.drm = new.drm;
+    }
+    if let Some(kind) = new.other_metadata.get("font_obfuscation") {
+        existing
+            .other_metadata
+            .entry("font_obfuscation".into())
+            .or_insert_with(|| kind.clone());
+    }
+}
+
+/// Resolve an `ID|PATH` argument to the index of one non-stale entry.
+/// Accepts an entry id or a unique prefix of it (at least 4 hex digits), a
+/// full path (as given or made absolute), or a root-relative path.
+pub fn find_entry(db: &BooksDb, key: &str) -> Result<usize> {
+    let abs = absolute(Path::new(key)).to_string_lossy().to_string();
+    let by_path: Vec<usize> = live(db)
+        .filter(|(_, b)| {
+            b.full_path == key || b.full_path == abs || b.rel_path.as_deref() == Some(key)
+        })
+        .map(|(i, _)| i)
+        .collect();
+    let hits = if !by_path.is_empty() {
+        by_path
+    } else if key.len() >= 4 && key.chars().all(|c| c.is_ascii_hexdigit()) {
+        let key = key.to_ascii_lowercase();
+        live(db)
+            .filter(|(_, b)| b.id().starts_with(&key))
+            .map(|(i, _)| i)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    match hits.as_slice() {
+        [i] => Ok(*i),
+        [] => bail!(
+            "no entry matches {key:?} (use an id from `export --fields id,full_path` or a path)"
+        ),
+        _ => bail!(
+            "{key:?} matches {} entries; use a longer id or the full path",
+            hits.len()
+        ),
+    }
+}
+
+fn live(db: &BooksDb) -> impl Iterator<Item = (usize, &BookEntry)> {
+    db.books.iter().enumerate().filter(|(_, b)| !b.stale)
+}
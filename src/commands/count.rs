@@ -2,12 +2,16 @@ use anyhow::Result;
 use tracing::info;
 
 use crate::model::BooksDb;
+use crate::report::Report;
 
-/// Print the number of entries in the current DB.
-pub fn cmd_count(db: &BooksDb) -> Result<()> {
+/// Print the number of entries in the current DB (`entries` of the JSON
+/// result with `--output json`).
+pub fn cmd_count(db: &BooksDb, report: &Report) -> Result<()> {
     let n = db.books.len();
     info!("DB entries: {}", n);
     // Also print to stdout in case user pipes/greps:
-    println!("{}", n);
+    if !report.json {
+        println!("{}", n);
+    }
     Ok(())
 }
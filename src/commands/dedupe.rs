@@ -0,0 +1,204 @@
+use anyhow::Result;
+use humansize::{DECIMAL, format_size};
+use rayon::prelude::*;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::archive::{self, Members};
+use crate::hash;
+use crate::model::{BookEntry, BooksDb};
+use crate::remote::{self, RangeReader};
+use crate::report::{self, Report};
+use crate::volume::Volumes;
+
+/// Report groups of duplicate files among present, non-stale entries
+/// (entries on offline volumes are left out).
+/// - Candidates are grouped by (size, quick_hash); only groups with more than
+///   one member are escalated to a full `xxh3_file`.
+/// - Entries sharing a content_hash but not an xxhash are reported as
+///   repackaged copies of the same book.
+///
+/// Hashes computed along the way are written back to the DB. Groups go to
+/// stdout, or under `details.groups` with `--output json`.
+pub fn cmd_dedupe(db: &mut BooksDb, report: &mut Report) -> Result<()> {
+    let mut vols = Volumes::new();
+    let live: Vec<usize> = db
+        .books
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| !b.stale && !b.missing && !vols.is_offline(b))
+        .map(|(i, _)| i)
+        .collect();
+
+    // 1) Quick hashes for entries that lack one
+    let need_quick: Vec<usize> = live
+        .iter()
+        .copied()
+        .filter(|&i| db.books[i].quick_hash.is_none())
+        .collect();
+    let members = Members::new(need_quick.iter().map(|&i| &db.books[i]));
+    fill_hashes(
+        db,
+        &need_quick,
+        |e| quick_hash(e, &members),
+        |b, h| b.quick_hash = Some(h),
+    );
+
+    let mut by_quick: BTreeMap<(u64, u128), Vec<usize>> = BTreeMap::new();
+    for &i in &live {
+        let b = &db.books[i];
+        if let Some(q) = b.quick_hash {
+            by_quick.entry((b.size_bytes, q)).or_default().push(i);
+        }
+    }
+    let candidates: Vec<usize> = by_quick
+        .into_values()
+        .filter(|g| g.len() > 1)
+        .flatten()
+        .collect();
+    info!(
+        "dedupe: {} live entr(y/ies), {} quick-hash candidate(s)",
+        live.len(),
+        candidates.len()
+    );
+
+    // 2) Escalate collisions to the full hash
+    let need_full: Vec<usize> = candidates
+        .iter()
+        .copied()
+        .filter(|&i| db.books[i].xxhash.is_none())
+        .collect();
+    let members = Members::new(need_full.iter().map(|&i| &db.books[i]));
+    fill_hashes(
+        db,
+        &need_full,
+        |e| full_hash(e, &members),
+        |b, h| b.xxhash = Some(h),
+    );
+
+    let mut by_full: BTreeMap<u128, Vec<usize>> = BTreeMap::new();
+    for &i in &candidates {
+        if let Some(h) = db.books[i].xxhash {
+            by_full.entry(h).or_default().push(i);
+        }
+    }
+
+    // 3) Same book inside, different packaging
+    let mut by_content: BTreeMap<u128, BTreeMap<u128, usize>> = BTreeMap::new();
+    for &i in &live {
+        let b = &db.books[i];
+        if let (Some(c), Some(h)) = (b.content_hash, b.xxhash) {
+            by_content.entry(c).or_default().entry(h).or_insert(i);
+        }
+    }
+
+    let mut groups = 0usize;
+    let mut redundant = 0usize;
+    let mut reclaimable = 0u64;
+    let mut listed = Vec::new();
+    let path = |i: &usize| db.books[*i].full_path.as_str();
+    for (h, idxs) in by_full.iter().filter(|(_, g)| g.len() > 1) {
+        groups += 1;
+        redundant += idxs.len() - 1;
+        let size = db.books[idxs[0]].size_bytes;
+        reclaimable += size * (idxs.len() as u64 - 1);
+        if report.json {
+            listed.push(json!({
+                "kind": "identical",
+                "hash": format!("{h:032x}"),
+                "size_bytes": size,
+                "paths": idxs.iter().map(path).collect::<Vec<_>>(),
+            }));
+            continue;
+        }
+        println!(
+            "# identical {:032x} ({} files, {} each)",
+            h,
+            idxs.len(),
+            format_size(size, DECIMAL)
+        );
+        for &i in idxs {
+            println!("{}", db.books[i].full_path);
+        }
+        println!();
+    }
+    for (c, variants) in by_content.iter().filter(|(_, v)| v.len() > 1) {
+        groups += 1;
+        redundant += variants.len() - 1;
+        if report.json {
+            listed.push(json!({
+                "kind": "same-content",
+                "hash": format!("{c:032x}"),
+                "paths": variants.values().map(path).collect::<Vec<_>>(),
+            }));
+            continue;
+        }
+        println!("# same content {:032x} ({} packagings)", c, variants.len());
+        for &i in variants.values() {
+            println!("{}", db.books[i].full_path);
+        }
+        println!();
+    }
+
+    info!(
+        "dedupe summary → groups: {}; redundant files: {}; reclaimable (identical): {}",
+        groups,
+        redundant,
+        format_size(reclaimable, DECIMAL)
+    );
+    report.detail("groups", listed);
+    report.detail("redundant", redundant);
+    report.detail("reclaimable_bytes", reclaimable);
+    Ok(())
+}
+
+/// XXH3 of an entry's bytes, wherever they live (file, bundle member, remote).
+fn full_hash(e: &BookEntry, members: &Members) -> Result<u128> {
+    if remote::is_remote(e) {
+        let (src, path) = remote::locate(e)?;
+        hash::xxh3_reader(src.open(&path)?)
+    } else if archive::is_member(e) {
+        Ok(members.read(e)?.xxhash)
+    } else {
+        hash::xxh3_file(Path::new(&e.full_path))
+    }
+}
+
+/// Quick hash of an entry; remote files are sampled with ranged reads.
+fn quick_hash(e: &BookEntry, members: &Members) -> Result<u128> {
+    if remote::is_remote(e) {
+        hash::quick_hash_reader(RangeReader::for_entry(e)?, e.size_bytes)
+    } else if archive::is_member(e) {
+        let m = members.read(e)?;
+        hash::quick_hash_reader(m.reader()?, m.size)
+    } else {
+        hash::quick_hash(Path::new(&e.full_path))
+    }
+}
+
+/// Compute `hasher` in parallel for the given entries and store successes via `apply`.
+fn fill_hashes<H, A>(db: &mut BooksDb, idxs: &[usize], hasher: H, apply: A)
+where
+    H: Fn(&BookEntry) -> Result<u128> + Sync,
+    A: Fn(&mut BookEntry, u128),
+{
+    let books = &db.books;
+    let results: Vec<(usize, Option<u128>)> = idxs
+        .par_iter()
+        .map(|&i| match hasher(&books[i]) {
+            Ok(h) => (i, Some(h)),
+            Err(e) => {
+                warn!("dedupe: failed to hash {}: {}", books[i].full_path, e);
+                report::file_error(&books[i].full_path, &e);
+                (i, None)
+            }
+        })
+        .collect();
+    for (i, h) in results {
+        if let Some(h) = h {
+            apply(&mut db.books[i], h);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::{Result, bail};
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::path::Path;
+use tracing::info;
+
+use crate::commands::common::same_content;
+use crate::db::load_db;
+use crate::model::{BookEntry, MatchBy};
+use crate::report::{Report, changed_fields};
+use crate::roots;
+
+/// Hash fields that one side may simply not have computed yet.
+const HASH_FIELDS: &[&str] = &["xxhash", "content_hash", "quick_hash"];
+
+/// Compare two DB files. Stale entries (history) are left out on both sides.
+/// - `--by path`: entries pair up by `full_path`; B's rooted entries are
+///   resolved at A's mountpoints, as `merge` does
+/// - `--by hash`: by full hash (same path first), then quick hash + size
+///   for entries lacking one, then by path for entries with no hash at all;
+///   several copies of one file pair up in path order
+///
+/// Paired entries differ if their content does (same rules as `merge`) or
+/// any other field does. A hash recorded on one side only is not a
+/// difference.
+pub fn cmd_diff(a_path: &Path, b_path: &Path, by: MatchBy, report: &mut Report) -> Result<()> {
+    for p in [a_path, b_path] {
+        if !p.exists() {
+            bail!("no such DB: {}", p.display());
+        }
+    }
+    let a = load_db(a_path)?;
+    let mut b = load_db(b_path)?;
+    for e in &mut b.books {
+        roots::resolve_entry(&a.roots, e);
+    }
+    let mut left: Vec<&BookEntry> = a.books.iter().filter(|e| !e.stale).collect();
+    let mut right: Vec<&BookEntry> = b.books.iter().filter(|e| !e.stale).collect();
+    left.sort_by(|x, y| x.full_path.cmp(&y.full_path));
+    right.sort_by(|x, y| x.full_path.cmp(&y.full_path));
+
+    let mut pairs = Vec::new();
+    if by == MatchBy::Hash {
+        // Unmoved files first, so a copy elsewhere does not take their place
+        pairs.extend(pair(&mut left, &mut right, |e| {
+            e.xxhash.map(|h| (h, e.full_path.as_str()))
+        }));
+        pairs.extend(pair(&mut left, &mut right, |e| e.xxhash));
+        pairs.extend(pair(&mut left, &mut right, |e| {
+            e.quick_hash.map(|q| (q, e.size_bytes))
+        }));
+    }
+    pairs.extend(pair(&mut left, &mut right, |e| Some(e.full_path.as_str())));
+
+    let mut same = 0usize;
+    let mut changed = Vec::new();
+    for (x, y) in pairs {
+        let fields: Vec<&str> = changed_fields(x, y)
+            .into_iter()
+            .filter(|f| !(HASH_FIELDS.contains(f) && same_content(x, y)))
+            .collect();
+        if fields.is_empty() {
+            same += 1;
+            continue;
+        }
+        let content = if same_content(x, y) {
+            "same"
+        } else if x.content_hash.is_some() && x.content_hash == y.content_hash {
+            "repackaged"
+        } else {
+            "changed"
+        };
+        changed.push((x, y, content, fields));
+    }
+    changed.sort_by(|p, q| p.0.full_path.cmp(&q.0.full_path));
+
+    info!(
+        "diff: {} only in {}, {} only in {}, {} differ, {} identical",
+        left.len(),
+        a_path.display(),
+        right.len(),
+        b_path.display(),
+        changed.len(),
+        same
+    );
+    if report.json {
+        let paths = |v: &[&BookEntry]| v.iter().map(|e| e.full_path.clone()).collect::<Vec<_>>();
+        let changed: Vec<_> = changed
+            .iter()
+            .map(|(x, y, content, fields)| {
+                json!({ "a": x.full_path, "b": y.full_path, "content": content, "fields": fields })
+            })
+            .collect();
+        report.detail("a", a_path.display().to_string());
+        report.detail("b", b_path.display().to_string());
+        report.detail("only_a", paths(&left));
+        report.detail("only_b", paths(&right));
+        report.detail("changed", changed);
+        report.detail("identical", same);
+        return Ok(());
+    }
+    for e in &left {
+        println!("< {}", e.full_path);
+    }
+    for e in &right {
+        println!("> {}", e.full_path);
+    }
+    for (x, y, content, fields) in &changed {
+        let path = if x.full_path == y.full_path {
+            x.full_path.clone()
+        } else {
+            format!("{} -> {}", x.full_path, y.full_path)
+        };
+        let note = if *content == "same" {
+            String::new()
+        } else {
+            format!(" [content {content}]")
+        };
+        println!("~ {path}: {}{note}", fields.join(", "));
+    }
+    Ok(())
+}
+
+/// Pair entries of `left` and `right` with equal keys, in order, and remove
+/// them from both lists. Entries without a key stay for the next pass.
+fn pair<'a, K: Hash + Eq>(
+    left: &mut Vec<&'a BookEntry>,
+    right: &mut Vec<&'a BookEntry>,
+    key: impl Fn(&'a BookEntry) -> Option<K>,
+) -> Vec<(&'a BookEntry, &'a BookEntry)> {
+    let mut by_key: HashMap<K, VecDeque<usize>> = HashMap::new();
+    for (i, e) in right.iter().enumerate() {
+        if let Some(k) = key(e) {
+            by_key.entry(k).or_default().push_back(i);
+        }
+    }
+    let mut taken = vec![false; right.len()];
+    let mut pairs = Vec::new();
+    left.retain(|x| {
+        let Some(j) = key(x).and_then(|k| by_key.get_mut(&k)?.pop_front()) else {
+            return true;
+        };
+        taken[j] = true;
+        pairs.push((*x, right[j]));
+        false
+    });
+    let mut taken = taken.into_iter();
+    right.retain(|_| !taken.next().unwrap_or(false));
+    pairs
+}
@@ -0,0 +1,80 @@
+use anyhow::{Result, bail};
+use std::path::PathBuf;
+use tracing::info;
+
+use crate::archive::{self, Members};
+use crate::commands::check::check_entry;
+use crate::commands::common::find_entry;
+use crate::hash;
+use crate::model::{BooksDb, FileFormat};
+use crate::opf_edit::{self, MetaEdit};
+use crate::remote;
+
+/// Edit one entry's metadata in the DB; with `write_file`, also rewrite the
+/// OPF inside the EPUB. The repacked file is then re-checked like `check`
+/// does: the old record goes stale and a fresh one (new hashes) is added.
+pub fn cmd_edit(db: &mut BooksDb, target: &str, edit: &MetaEdit, write_file: bool) -> Result<()> {
+    if edit.is_empty() {
+        bail!("nothing to edit (use --title, --author and/or --series)");
+    }
+    let idx = find_entry(db, target)?;
+
+    if !write_file {
+        edit.apply(&mut db.books[idx]);
+        info!("Edited (DB only): {}", db.books[idx].full_path);
+        return Ok(());
+    }
+
+    let existing = &db.books[idx];
+    let path = PathBuf::from(&existing.full_path);
+    if existing.format != FileFormat::Epub {
+        bail!("--write-file only supports EPUB: {}", path.display());
+    }
+    if archive::is_member(existing) {
+        bail!(
+            "--write-file cannot modify a book inside an archive: {}",
+            path.display()
+        );
+    }
+    if remote::is_remote(existing) {
+        bail!(
+            "--write-file cannot modify a remote book: {}",
+            path.display()
+        );
+    }
+    if existing.missing || !path.exists() {
+        bail!("file is missing: {}", path.display());
+    }
+    // Never overwrite content the DB has not seen.
+    if let Some(known) = existing.xxhash
+        && hash::xxh3_file(&path)? != known
+    {
+        bail!(
+            "{} changed since it was indexed; run `check` first",
+            path.display()
+        );
+    }
+
+    opf_edit::write_epub_opf(&path, edit)?;
+    info!("Rewrote OPF in {}", path.display());
+
+    let existing = &mut db.books[idx];
+    match check_entry(existing, None, &Members::default()) {
+        Some(mut fresh) => {
+            // Keep what the file cannot carry (imported tags, ratings, …).
+            for (k, v) in &existing.other_metadata {
+                fresh
+                    .other_metadata
+                    .entry(k.clone())
+                    .or_insert_with(|| v.clone());
+            }
+            fresh.description = fresh.description.or(existing.description.clone());
+            fresh.publisher = fresh.publisher.or(existing.publisher.clone());
+            fresh.publish_date = fresh.publish_date.or(existing.publish_date.clone());
+            edit.apply(&mut fresh);
+            db.books.push(fresh);
+        }
+        None => edit.apply(existing),
+    }
+    Ok(())
+}
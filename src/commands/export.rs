@@ -0,0 +1,203 @@
+use anyhow::{Context, Result, bail};
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+use crate::biblio;
+use crate::filter::EntryFilter;
+use crate::model::{BookEntry, BooksDb, ExportFormat};
+use crate::report::Report;
+
+/// Flat columns, in `BookEntry` order. `other_metadata` is exported as one
+/// dynamic column per key, named `other_metadata.<key>`.
+pub const FIELDS: &[&str] = &[
+    "id",
+    "full_path",
+    "root",
+    "rel_path",
+    "uri_path",
+    "protocol",
+    "filename",
+    "format",
+    "size_bytes",
+    "xxhash",
+    "content_hash",
+    "quick_hash",
+    "etag",
+    "volume",
+    "date_found",
+    "missing",
+    "stale",
+    "drm",
+    "validation",
+    "validation_issues",
+    "title",
+    "author",
+    "description",
+    "chapters",
+    "publish_date",
+    "publisher",
+];
+
+const META_PREFIX: &str = "other_metadata.";
+
+/// Write the entries selected by `filter` as CSV/TSV (header row, RFC 4180
+/// quoting) or JSON Lines. `fields` empty = all flat fields plus every
+/// `other_metadata` key present in the selection; `other_metadata.*` expands
+/// to those keys. BibTeX / CSL-JSON / RIS write one citation per entry and
+/// ignore `fields`. With `--output json`, stdout carries the result object,
+/// so `out` is required.
+pub fn cmd_export(
+    db: &BooksDb,
+    format: ExportFormat,
+    fields: &[String],
+    filter: &EntryFilter,
+    out: Option<PathBuf>,
+    report: &mut Report,
+) -> Result<()> {
+    if report.json && out.is_none() {
+        bail!("export with --output json needs --out FILE (stdout carries the result object)");
+    }
+    let selected: Vec<&BookEntry> = db.books.iter().filter(|e| filter.matches(e)).collect();
+    let meta_keys: BTreeSet<&str> = selected
+        .iter()
+        .flat_map(|e| e.other_metadata.keys().map(String::as_str))
+        .collect();
+    let all_meta = || meta_keys.iter().map(|k| format!("{META_PREFIX}{k}"));
+
+    let mut columns: Vec<String> = Vec::new();
+    if fields.is_empty() {
+        columns.extend(FIELDS.iter().map(|f| f.to_string()));
+        columns.extend(all_meta());
+    } else {
+        for f in fields {
+            match f.as_str() {
+                "other_metadata" | "other_metadata.*" => columns.extend(all_meta()),
+                f if f.starts_with(META_PREFIX) || FIELDS.contains(&f) => {
+                    columns.push(f.to_string())
+                }
+                f => bail!("unknown field {f:?}; known: {}", FIELDS.join(", ")),
+            }
+        }
+    }
+
+    let sink: Box<dyn Write> = match &out {
+        Some(p) => Box::new(File::create(p).with_context(|| format!("create {}", p.display()))?),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut w = BufWriter::new(sink);
+
+    match format {
+        ExportFormat::Bibtex | ExportFormat::CslJson | ExportFormat::Ris => {
+            if !fields.is_empty() {
+                warn!("export: --fields is ignored for bibliography formats");
+            }
+            let cites = biblio::citations(&db.books, &selected);
+            match format {
+                ExportFormat::Bibtex => w.write_all(biblio::to_bibtex(&cites).as_bytes())?,
+                ExportFormat::Ris => w.write_all(biblio::to_ris(&cites).as_bytes())?,
+                _ => {
+                    serde_json::to_writer_pretty(&mut w, &biblio::to_csl_json(&cites))?;
+                    writeln!(w)?;
+                }
+            }
+        }
+        ExportFormat::Csv | ExportFormat::Tsv => {
+            let mut wr = csv::WriterBuilder::new()
+                .delimiter(if format == ExportFormat::Tsv {
+                    b'\t'
+                } else {
+                    b','
+                })
+                .from_writer(&mut w);
+            wr.write_record(&columns)?;
+            for e in &selected {
+                wr.write_record(columns.iter().map(|c| cell_text(field_value(e, c))))?;
+            }
+            wr.flush()?;
+        }
+        ExportFormat::Jsonl => {
+            for e in &selected {
+                let obj: Map<String, Value> = columns
+                    .iter()
+                    .map(|c| (c.clone(), field_value(e, c)))
+                    .collect();
+                serde_json::to_writer(&mut w, &obj)?;
+                writeln!(w)?;
+            }
+        }
+    }
+    w.flush()?;
+
+    info!(
+        "export: {} entr(y/ies) × {} column(s) → {}",
+        selected.len(),
+        columns.len(),
+        out.as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "stdout".into())
+    );
+    report.detail("exported", selected.len());
+    report.detail("out", &out);
+    Ok(())
+}
+
+/// One flat field of an entry. 128-bit hashes are strings (they overflow
+/// JSON/pandas integers); `chapters` stays a list for JSON.
+pub fn field_value(e: &BookEntry, field: &str) -> Value {
+    let opt = |s: &Option<String>| s.clone().map(Value::String).unwrap_or(Value::Null);
+    let hash = |h: Option<u128>| {
+        h.map(|h| Value::String(h.to_string()))
+            .unwrap_or(Value::Null)
+    };
+    match field {
+        "id" => Value::String(e.id()),
+        "full_path" => Value::String(e.full_path.clone()),
+        "root" => opt(&e.root),
+        "rel_path" => opt(&e.rel_path),
+        "uri_path" => Value::String(e.uri_path.clone()),
+        "protocol" => Value::String(e.protocol.clone()),
+        "filename" => Value::String(e.filename.clone()),
+        "format" => serde_json::to_value(e.format).unwrap_or(Value::Null),
+        "size_bytes" => Value::from(e.size_bytes),
+        "xxhash" => hash(e.xxhash),
+        "content_hash" => hash(e.content_hash),
+        "quick_hash" => hash(e.quick_hash),
+        "etag" => opt(&e.etag),
+        "volume" => opt(&e.volume),
+        "date_found" => Value::String(e.date_found.clone()),
+        "missing" => Value::Bool(e.missing),
+        "stale" => Value::Bool(e.stale),
+        "drm" => serde_json::to_value(e.drm).unwrap_or(Value::Null),
+        "validation" => serde_json::to_value(e.validation).unwrap_or(Value::Null),
+        "validation_issues" => Value::from(e.validation_issues.clone()),
+        "title" => opt(&e.title),
+        "author" => opt(&e.author),
+        "description" => opt(&e.description),
+        "chapters" => Value::from(e.chapters.clone()),
+        "publish_date" => opt(&e.publish_date),
+        "publisher" => opt(&e.publisher),
+        f => f
+            .strip_prefix(META_PREFIX)
+            .and_then(|k| e.other_metadata.get(k))
+            .map(|v| Value::String(v.clone()))
+            .unwrap_or(Value::Null),
+    }
+}
+
+/// Spreadsheet cell text: null → empty, lists joined with "; ".
+fn cell_text(v: Value) -> String {
+    match v {
+        Value::Null => String::new(),
+        Value::String(s) => s,
+        Value::Array(items) => items
+            .into_iter()
+            .map(cell_text)
+            .collect::<Vec<_>>()
+            .join("; "),
+        other => other.to_string(),
+    }
+}
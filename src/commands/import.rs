@@ -0,0 +1,385 @@
+//! `import`: bring books in from another catalog application.
+//!
+//! Calibre: `metadata.db` is opened read-only and is the source of truth for
+//! authors, series, tags, rating, identifiers, publisher, comments and
+//! languages; each book's `metadata.opf` fills whatever the DB row lacks.
+//! Every format file becomes its own entry, hashed like `load` does.
+
+use anyhow::{Result, bail};
+use rayon::prelude::*;
+use rusqlite::{Connection, OpenFlags};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use crate::args::ImportSource;
+use crate::commands::common::merge_entry;
+use crate::commands::load::build_entry;
+use crate::metadata;
+use crate::model::{BookEntry, BooksDb, EpubMeta, FileFormat};
+use crate::report;
+use crate::roots;
+use crate::volume::Volumes;
+
+pub fn cmd_import(db: &mut BooksDb, source: ImportSource) -> Result<()> {
+    match source {
+        ImportSource::Calibre {
+            library,
+            name,
+            no_hash,
+            quick_hash,
+        } => {
+            if let Some(name) = name {
+                roots::set_root(db, &name, &library)?;
+            }
+            import_calibre(db, &library, no_hash, quick_hash)
+        }
+    }
+}
+
+/// One row of Calibre's `books` table plus its linked tables.
+#[derive(Debug, Default)]
+struct CalibreBook {
+    id: i64,
+    title: String,
+    author_sort: Option<String>,
+    path: String,
+    pubdate: Option<String>,
+    series_index: Option<f64>,
+    uuid: Option<String>,
+    authors: Vec<String>,
+    series: Option<String>,
+    tags: Vec<String>,
+    rating: Option<u32>,
+    identifiers: Vec<(String, String)>,
+    publisher: Option<String>,
+    comments: Option<String>,
+    languages: Vec<String>,
+    formats: Vec<(String, String)>,
+}
+
+fn import_calibre(db: &mut BooksDb, library: &Path, no_hash: bool, quick_only: bool) -> Result<()> {
+    let db_file = library.join("metadata.db");
+    if !db_file.is_file() {
+        bail!(
+            "{} is not a Calibre library (no metadata.db)",
+            library.display()
+        );
+    }
+    let books = read_calibre_db(&db_file)?;
+    info!(
+        "{}: {} book(s) in metadata.db",
+        library.display(),
+        books.len()
+    );
+
+    // (file, metadata) per supported format file
+    let mut files: Vec<(PathBuf, EpubMeta)> = Vec::new();
+    let mut unsupported: BTreeMap<String, usize> = BTreeMap::new();
+    let mut absent = 0usize;
+    for book in &books {
+        let dir = library.join(&book.path);
+        let opf = fs::read_to_string(dir.join("metadata.opf"))
+            .ok()
+            .and_then(|xml| metadata::parse_opf(&xml).ok());
+        for (format, name) in &book.formats {
+            if supported_format(format).is_none() {
+                *unsupported.entry(format.clone()).or_default() += 1;
+                continue;
+            }
+            let file = dir.join(format!("{}.{}", name, format.to_ascii_lowercase()));
+            if !file.is_file() {
+                warn!(
+                    "Calibre book {} lists a missing file: {}",
+                    book.id,
+                    file.display()
+                );
+                report::file_error(
+                    file.display(),
+                    format!("listed by Calibre book {}, not on disk", book.id),
+                );
+                absent += 1;
+                continue;
+            }
+            files.push((file, calibre_meta(book, opf.clone())));
+        }
+    }
+
+    let mut new_entries: Vec<BookEntry> = files
+        .par_iter()
+        .map(|(p, meta)| {
+            let mut e = build_entry(p, no_hash, quick_only);
+            apply_meta(&mut e, meta.clone());
+            roots::assign(&db.roots, &mut e);
+            e
+        })
+        .collect();
+
+    let mut vols = Volumes::new();
+    let (mut added, mut refreshed) = (0usize, 0usize);
+    for e in new_entries.iter_mut() {
+        e.volume = vols.volume_of(Path::new(&e.full_path));
+        let before = db.books.len();
+        merge_entry(db, e);
+        if db.books.len() > before {
+            added += 1;
+        } else if let Some(live) = db
+            .books
+            .iter_mut()
+            .find(|b| b.full_path == e.full_path && !b.stale)
+        {
+            // Unchanged file: Calibre's (possibly edited) metadata still wins.
+            live.title = e.title.clone();
+            live.author = e.author.clone();
+            live.description = e.description.clone();
+            live.publish_date = e.publish_date.clone();
+            live.publisher = e.publisher.clone();
+            // A key cleared in Calibre since the last import goes away too
+            live.other_metadata
+                .retain(|k, _| !CALIBRE_KEYS.contains(&k.as_str()));
+            live.other_metadata.extend(e.other_metadata.clone());
+            refreshed += 1;
+        }
+    }
+
+    for (format, n) in &unsupported {
+        info!("Skipped {} {} file(s) (format not supported)", n, format);
+    }
+    info!(
+        "import summary → books: {}; files: {} (added: {}, refreshed: {}); missing files: {}; unsupported: {}",
+        books.len(),
+        new_entries.len(),
+        added,
+        refreshed,
+        absent,
+        unsupported.values().sum::<usize>()
+    );
+    Ok(())
+}
+
+fn read_calibre_db(db_file: &Path) -> Result<Vec<CalibreBook>> {
+    // Read-only: never touch (or lock for writing) the user's Calibre library.
+    let conn = Connection::open_with_flags(
+        db_file,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+
+    let mut books: Vec<CalibreBook> = conn
+        .prepare(
+            "SELECT id, title, author_sort, path, pubdate, series_index, uuid FROM books ORDER BY id",
+        )?
+        .query_map([], |r| {
+            Ok(CalibreBook {
+                id: r.get(0)?,
+                title: r.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                author_sort: r.get(2)?,
+                path: r.get(3)?,
+                pubdate: r.get(4)?,
+                series_index: r.get(5)?,
+                uuid: r.get(6)?,
+                ..CalibreBook::default()
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut authors = grouped(
+        &conn,
+        "SELECT l.book, a.name FROM books_authors_link l JOIN authors a ON a.id = l.author ORDER BY l.id",
+    )?;
+    let mut series = grouped(
+        &conn,
+        "SELECT l.book, s.name FROM books_series_link l JOIN series s ON s.id = l.series",
+    )?;
+    let mut tags = grouped(
+        &conn,
+        "SELECT l.book, t.name FROM books_tags_link l JOIN tags t ON t.id = l.tag ORDER BY t.name",
+    )?;
+    let mut ratings = grouped(
+        &conn,
+        "SELECT l.book, CAST(r.rating AS TEXT) FROM books_ratings_link l JOIN ratings r ON r.id = l.rating",
+    )?;
+    let mut identifiers = grouped(
+        &conn,
+        "SELECT book, type || ':' || val FROM identifiers ORDER BY type",
+    )?;
+    let mut publishers = grouped(
+        &conn,
+        "SELECT l.book, p.name FROM books_publishers_link l JOIN publishers p ON p.id = l.publisher",
+    )?;
+    let mut comments = grouped(&conn, "SELECT book, text FROM comments")?;
+    let mut languages = grouped(
+        &conn,
+        "SELECT l.book, g.lang_code FROM books_languages_link l JOIN languages g ON g.id = l.lang_code ORDER BY l.item_order",
+    )?;
+    let mut formats = grouped(
+        &conn,
+        "SELECT book, format || ':' || name FROM data ORDER BY format",
+    )?;
+
+    for b in books.iter_mut() {
+        let take = |m: &mut HashMap<i64, Vec<String>>| m.remove(&b.id).unwrap_or_default();
+        b.authors = take(&mut authors);
+        b.series = take(&mut series).into_iter().next();
+        b.tags = take(&mut tags);
+        // Calibre stores 0–10 (half stars); 0 means unrated
+        b.rating = take(&mut ratings)
+            .first()
+            .and_then(|r| r.parse().ok())
+            .filter(|&r| r > 0);
+        b.identifiers = take(&mut identifiers)
+            .into_iter()
+            .filter_map(|s| {
+                s.split_once(':')
+                    .map(|(t, v)| (t.to_string(), v.to_string()))
+            })
+            .collect();
+        b.publisher = take(&mut publishers).into_iter().next();
+        b.comments = take(&mut comments).into_iter().next();
+        b.languages = take(&mut languages);
+        b.formats = take(&mut formats)
+            .into_iter()
+            .filter_map(|s| {
+                s.split_once(':')
+                    .map(|(f, n)| (f.to_string(), n.to_string()))
+            })
+            .collect();
+    }
+    debug!("Read {} Calibre book row(s)", books.len());
+    Ok(books)
+}
+
+/// Run a two-column `(book id, text)` query and group the texts by book.
+fn grouped(conn: &Connection, sql: &str) -> Result<HashMap<i64, Vec<String>>> {
+    let mut out: HashMap<i64, Vec<String>> = HashMap::new();
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map([], |r| {
+        Ok((r.get::<_, i64>(0)?, r.get::<_, Option<String>>(1)?))
+    })?;
+    for row in rows {
+        if let (id, Some(text)) = row? {
+            out.entry(id).or_default().push(text);
+        }
+    }
+    Ok(out)
+}
+
+/// `other_metadata` keys that `calibre_meta` fills from the Calibre library.
+const CALIBRE_KEYS: &[&str] = &[
+    "creators",
+    "author_sort",
+    "series",
+    "series_index",
+    "tags",
+    "rating",
+    "identifier",
+    "identifiers",
+    "language",
+    "formats",
+    "calibre_id",
+    "calibre_uuid",
+];
+
+/// Calibre's fields, falling back to the book's metadata.opf, as `EpubMeta`.
+fn calibre_meta(book: &CalibreBook, opf: Option<EpubMeta>) -> EpubMeta {
+    let opf = opf.unwrap_or_default();
+    let mut other = opf.other_metadata;
+
+    if book.authors.len() > 1 {
+        other.insert("creators".into(), book.authors.join("; "));
+    }
+    if let Some(s) = &book.author_sort {
+        other.insert("author_sort".into(), s.clone());
+    }
+    if let Some(s) = &book.series {
+        other.insert("series".into(), s.clone());
+        if let Some(i) = book.series_index {
+            other.insert("series_index".into(), format_number(i));
+        }
+    }
+    if !book.tags.is_empty() {
+        other.insert("tags".into(), book.tags.join("; "));
+    }
+    if let Some(r) = book.rating {
+        other.insert("rating".into(), format_number(f64::from(r) / 2.0));
+    }
+    if !book.identifiers.is_empty() {
+        let isbn = book.identifiers.iter().find(|(t, _)| t == "isbn");
+        let (t, v) = isbn.unwrap_or(&book.identifiers[0]);
+        let primary = if t == "isbn" {
+            format!("urn:isbn:{v}")
+        } else {
+            format!("{t}:{v}")
+        };
+        other.insert("identifier".into(), primary);
+        let all: Vec<String> = book
+            .identifiers
+            .iter()
+            .map(|(t, v)| format!("{t}:{v}"))
+            .collect();
+        other.insert("identifiers".into(), all.join("; "));
+    }
+    if let Some(lang) = book.languages.first() {
+        other.insert("language".into(), lang.clone());
+    }
+    let formats: Vec<&str> = book.formats.iter().map(|(f, _)| f.as_str()).collect();
+    other.insert("formats".into(), formats.join("; "));
+    other.insert("calibre_id".into(), book.id.to_string());
+    if let Some(u) = &book.uuid {
+        other.insert("calibre_uuid".into(), u.clone());
+    }
+
+    EpubMeta {
+        title: Some(book.title.clone())
+            .filter(|t| !t.is_empty())
+            .or(opf.title),
+        author: book.authors.first().cloned().or(opf.author),
+        description: book.comments.clone().or(opf.description),
+        chapters: opf.chapters,
+        // Calibre writes 0101-01-01 for "no date"
+        publish_date: book
+            .pubdate
+            .as_deref()
+            .filter(|d| !d.starts_with("0101"))
+            .map(|d| d.chars().take(10).collect())
+            .or(opf.publish_date),
+        publisher: book.publisher.clone().or(opf.publisher),
+        other_metadata: other,
+        drm: None,
+    }
+}
+
+fn apply_meta(e: &mut BookEntry, meta: EpubMeta) {
+    e.title = meta.title;
+    e.author = meta.author;
+    e.description = meta.description;
+    if !meta.chapters.is_empty() {
+        e.chapters = meta.chapters;
+    }
+    e.publish_date = meta.publish_date;
+    e.publisher = meta.publisher;
+    // Calibre's keys win; ones only the file has (`font_obfuscation`, …) stay
+    e.other_metadata.extend(meta.other_metadata);
+}
+
+/// "3" rather than "3.0"; Calibre's indexes and half-star ratings stay exact.
+fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 {
+        format!("{}", n as i64)
+    } else {
+        n.to_string()
+    }
+}
+
+/// Calibre format names we can index; later formats are skipped and counted.
+fn supported_format(calibre: &str) -> Option<FileFormat> {
+    match calibre {
+        "EPUB" => Some(FileFormat::Epub),
+        "PDF" => Some(FileFormat::Pdf),
+        "MOBI" | "AZW" | "AZW3" | "PRC" => Some(FileFormat::Mobi),
+        "FB2" => Some(FileFormat::Fb2),
+        "CBZ" | "CBR" | "CB7" => Some(FileFormat::Comic),
+        "DJVU" | "DJV" => Some(FileFormat::Djvu),
+        _ => None,
+    }
+}
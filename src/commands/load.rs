@@ -1,124 +1,246 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
 use rayon::prelude::*;
-use std::fs;
-use std::path::PathBuf;
-use tracing::info;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
 
+use crate::archive::{self, ArchiveKind};
 use crate::commands::common::merge_entry;
+use crate::container::{self, is_exploded_epub};
 use crate::hash;
 use crate::metadata;
 use crate::model::{BookEntry, BooksDb, FileFormat};
-use crate::scan::gather_epubs;
+use crate::remote::{self, RemoteFile, RemoteUri};
+use crate::report;
+use crate::roots;
+use crate::scan::{PathFilter, ScanOpts, gather_files, is_book_path};
 use crate::util::{file_uri, now_iso8601};
+use crate::volume::Volumes;
 
+/// How `load` treats what it finds.
+#[derive(Debug, Clone, Default)]
+pub struct LoadOpts {
+    pub no_hash: bool,
+    /// Only the quick hash; xxhash is left for `rehash`
+    pub quick_only: bool,
+    /// Also index the books inside .zip/.tar bundles
+    pub descend_archives: bool,
+}
+
+/// Scan `root` (registering it as root `name` when given), only the named root,
+/// or with neither, every named root in the DB. `root` may also be a
+/// `webdav://`, `webdavs://`, `sftp://` or `s3://` URI.
 pub fn cmd_load(
     db: &mut BooksDb,
     _db_path: &PathBuf,
-    root: PathBuf,
-    follow_symlinks: bool,
-    no_hash: bool,
+    root: Option<PathBuf>,
+    name: Option<String>,
+    scan: &ScanOpts,
+    opts: &LoadOpts,
 ) -> Result<()> {
-    let files = gather_epubs(&root, follow_symlinks)?; // now includes .epub and .pdf
-    info!("found {} candidate file(s)", files.len());
+    let dirs: Vec<PathBuf> = match (root, name) {
+        (Some(dir), Some(_)) if remote::is_remote_uri(&dir.to_string_lossy()) => {
+            bail!("named roots must be local directories: {}", dir.display())
+        }
+        (Some(dir), Some(name)) => {
+            roots::set_root(db, &name, &dir)?;
+            vec![roots::root_dir(&db.roots, &name)?.clone()]
+        }
+        (Some(dir), None) => vec![dir],
+        (None, Some(name)) => vec![roots::root_dir(&db.roots, &name)?.clone()],
+        (None, None) if db.roots.is_empty() => {
+            bail!("no DIR given and the DB has no named roots (use `load DIR --name NAME`)")
+        }
+        (None, None) => db.roots.values().cloned().collect(),
+    };
+    for dir in dirs {
+        let uri = dir.to_string_lossy();
+        if remote::is_remote_uri(&uri) {
+            load_remote(db, &uri, scan, opts)?;
+        } else {
+            load_dir(db, &dir, scan, opts)?;
+        }
+    }
+    Ok(())
+}
 
-    // Tally what we *found* by extension upfront
-    let mut found_epub: usize = 0;
-    let mut found_pdf: usize = 0;
+/// Scan a remote root and merge what was found into the DB. The scan
+/// filters apply to server paths; archives and exploded directories are
+/// not looked into.
+pub fn load_remote(db: &mut BooksDb, uri: &str, scan: &ScanOpts, opts: &LoadOpts) -> Result<()> {
+    let root = RemoteUri::parse(uri)?;
+    let src = remote::source(&root)?;
+    let filter = PathFilter::for_remote(Path::new(&root.path), scan)?;
+    let files: Vec<RemoteFile> = src
+        .list(&root.path)?
+        .into_iter()
+        .filter(|f| {
+            let p = Path::new(&f.path);
+            is_book_path(p) && (f.path == root.path || filter.admits(p))
+        })
+        .collect();
+    info!("{}: found {} candidate file(s)", uri, files.len());
 
     let new_entries: Vec<BookEntry> = files
         .par_iter()
-        .map(|p| {
-            let size = fs::metadata(p).map(|m| m.len()).unwrap_or(0);
-            let ext_lc = p
-                .extension()
-                .and_then(|e| e.to_str())
-                .map(|s| s.to_ascii_lowercase())
-                .unwrap_or_default();
-
-            let format = if ext_lc == "pdf" {
-                FileFormat::Pdf
-            } else {
-                FileFormat::Epub
-            };
-
-            // Count found by format
-            match format {
-                FileFormat::Epub => {
-                    // increment later once we’re back on the main thread to avoid locks,
-                    // but we can’t here—so we’ll do a second pass below if needed.
-                    // Instead, tally in a post-collect scan:
-                }
-                FileFormat::Pdf => {}
-            }
-
-            let meta = match format {
-                FileFormat::Epub => metadata::extract_epub_metadata(p).unwrap_or_default(),
-                FileFormat::Pdf => metadata::extract_pdf_metadata(p).unwrap_or_default(),
-            };
+        .map(|f| remote::build_entry(&root, &src, f, opts.no_hash, opts.quick_only))
+        .collect();
+    merge_found(db, new_entries);
+    Ok(())
+}
 
-            let hash = if no_hash {
-                None
-            } else {
-                hash::xxh3_file(p).ok()
-            };
+/// Scan one directory and merge what was found into the DB.
+pub fn load_dir(db: &mut BooksDb, root: &Path, scan: &ScanOpts, opts: &LoadOpts) -> Result<()> {
+    let LoadOpts {
+        no_hash,
+        quick_only,
+        descend_archives,
+    } = *opts;
+    // every indexed format, not just .epub; exploded EPUB directories; plus
+    // bundles when descending
+    let found = gather_files(root, scan, |p, is_dir| {
+        if is_dir {
+            is_exploded_epub(p)
+        } else {
+            is_book_path(p) || (descend_archives && ArchiveKind::of(p).is_some())
+        }
+    })?;
+    let (bundles, files): (Vec<PathBuf>, Vec<PathBuf>) = found
+        .into_iter()
+        .partition(|p| !is_book_path(p) && ArchiveKind::of(p).is_some() && p.is_file());
+    info!(
+        "{}: found {} candidate file(s){}",
+        root.display(),
+        files.len(),
+        if descend_archives {
+            format!(", {} archive(s)", bundles.len())
+        } else {
+            String::new()
+        }
+    );
 
-            BookEntry {
-                full_path: p.to_string_lossy().to_string(),
-                uri_path: file_uri(p),
-                protocol: "file".into(),
-                filename: p
-                    .file_name()
-                    .and_then(|s| s.to_str())
-                    .unwrap_or_default()
-                    .to_string(),
-                xxhash: hash,
-                date_found: now_iso8601(),
-                missing: false,
-                stale: false,
-                size_bytes: size,
-                format,
-                title: meta.title,
-                author: meta.author,
-                description: meta.description,
-                chapters: meta.chapters,
-                publish_date: meta.publish_date,
-                publisher: meta.publisher,
-                other_metadata: meta.other_metadata,
-            }
+    let mut new_entries: Vec<BookEntry> = files
+        .par_iter()
+        .map(|p| build_entry(p, no_hash, quick_only))
+        .chain(
+            bundles
+                .par_iter()
+                .flat_map_iter(|a| archive::build_entries(a, no_hash, quick_only)),
+        )
+        .map(|mut e| {
+            roots::assign(&db.roots, &mut e);
+            e
         })
         .collect();
 
-    // Now that we have formats, tally the “found” counts
-    for e in &new_entries {
-        match e.format {
-            FileFormat::Epub => found_epub += 1,
-            FileFormat::Pdf => found_pdf += 1,
-        }
+    // Volume lookups are memoized per directory; do them serially
+    let mut vols = Volumes::new();
+    for e in new_entries.iter_mut() {
+        e.volume = vols.volume_of(Path::new(&e.full_path));
     }
+    merge_found(db, new_entries);
+    Ok(())
+}
 
-    // Tally what we actually *added* after merge (as opposed to updated)
-    let mut added_epub: usize = 0;
-    let mut added_pdf: usize = 0;
+/// Merge freshly built entries into the DB and log per-format tallies.
+fn merge_found(db: &mut BooksDb, new_entries: Vec<BookEntry>) {
+    // Tally what we found, and what we actually *added* after merge (as
+    // opposed to updated), per format
+    let mut found: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut added: BTreeMap<&str, usize> = BTreeMap::new();
 
     for mut e in new_entries {
+        *found.entry(e.format.as_str()).or_default() += 1;
         let before = db.books.len();
         merge_entry(db, &mut e);
         let after = db.books.len();
         if after > before {
-            match e.format {
-                FileFormat::Epub => added_epub += 1,
-                FileFormat::Pdf => added_pdf += 1,
-            }
+            *added.entry(e.format.as_str()).or_default() += 1;
         }
     }
 
     // Final summary log
-    let found_total = found_epub + found_pdf;
-    let added_total = added_epub + added_pdf;
     info!(
-        "load summary → found: {} (epub={}, pdf={}); added: {} (epub={}, pdf={})",
-        found_total, found_epub, found_pdf, added_total, added_epub, added_pdf
+        "load summary → found: {} ({}); added: {} ({})",
+        found.values().sum::<usize>(),
+        per_format(&found),
+        added.values().sum::<usize>(),
+        per_format(&added)
     );
+}
 
-    Ok(())
+/// "epub=3, mobi=1"
+fn per_format(counts: &BTreeMap<&str, usize>) -> String {
+    if counts.is_empty() {
+        return "none".into();
+    }
+    counts
+        .iter()
+        .map(|(f, n)| format!("{f}={n}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Build a fresh `BookEntry` for one file (or exploded EPUB directory):
+/// format from extension, metadata, size and (unless `no_hash`) hashes.
+/// Shared by `load` and `watch`.
+pub fn build_entry(p: &Path, no_hash: bool, quick_only: bool) -> BookEntry {
+    let size = container::book_size(p);
+    // An exploded directory is an EPUB whatever it is named
+    let format = if p.is_dir() {
+        FileFormat::Epub
+    } else {
+        FileFormat::from_path(p).unwrap_or_default()
+    };
+    let meta = metadata::extract_metadata(p, format).unwrap_or_default();
+
+    let quick_hash = if no_hash {
+        None
+    } else {
+        hash::quick_hash(p).ok()
+    };
+    let (hash, content_hash) = if no_hash || quick_only {
+        (None, None)
+    } else {
+        let full = hash::xxh3_file(p)
+            .map_err(|e| {
+                warn!("load: failed to hash {}: {}", p.display(), e);
+                report::file_error(p.display(), &e);
+            })
+            .ok();
+        (full, hash::content_hash(p, format))
+    };
+
+    BookEntry {
+        full_path: p.to_string_lossy().to_string(),
+        root: None,
+        rel_path: None,
+        volume: None,
+        uri_path: file_uri(p),
+        protocol: "file".into(),
+        filename: p
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+            .to_string(),
+        xxhash: hash,
+        content_hash,
+        quick_hash,
+        etag: None,
+        date_found: now_iso8601(),
+        missing: false,
+        stale: false,
+        size_bytes: size,
+        format,
+        validation: None,
+        validation_issues: Vec::new(),
+        drm: meta.drm,
+        title: meta.title,
+        author: meta.author,
+        description: meta.description,
+        chapters: meta.chapters,
+        publish_date: meta.publish_date,
+        publisher: meta.publisher,
+        other_metadata: meta.other_metadata,
+    }
 }
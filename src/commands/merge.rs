@@ -5,22 +5,34 @@ use tracing::info;
 use crate::commands::common::merge_entry;
 use crate::db::load_db;
 use crate::model::BooksDb;
+use crate::roots;
 use crate::util::now_iso8601;
 
 /// Merge another JSON DB (produced by this program) into the current DB.
 /// - Entries are merged using the same rules as `load` (by full_path + xxhash).
 /// - If the other DB has entries for paths you don't have, they are added.
 /// - If both have the same path but content changed, the old one becomes stale+missing.
+/// - Entries under a named root are matched at our mountpoint for that root.
 pub fn cmd_merge(db: &mut BooksDb, other_db_path: PathBuf) -> Result<()> {
     let other = load_db(&other_db_path).unwrap_or_default();
     let n = other.books.len();
 
+    // Named roots: keep our mountpoints, adopt roots we don't know yet.
+    for (name, dir) in &other.roots {
+        if !db.roots.contains_key(name) {
+            db.roots.insert(name.clone(), dir.clone());
+            info!("root {}: adopted from other DB at {}", name, dir.display());
+        }
+    }
+
     // Treat the other DB's entries as incoming "candidates"
     for mut e in other.books.into_iter() {
         // Ensure date_found is set
         if e.date_found.is_empty() {
             e.date_found = now_iso8601();
         }
+        // Rooted entries resolve against our mountpoint for that root
+        roots::resolve_entry(&db.roots, &mut e);
         // Merge into current DB
         merge_entry(db, &mut e);
     }
@@ -1,18 +1,32 @@
 pub mod check;
 pub mod common;
 pub mod count;
+pub mod dedupe;
+pub mod diff;
+pub mod edit;
+pub mod export;
+pub mod import;
 pub mod load;
 pub mod merge;
+pub mod organize;
 pub mod prune;
 pub mod rehash;
+pub mod root;
+pub mod site;
+pub mod validate;
+pub mod watch;
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use rayon::ThreadPoolBuilder;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tracing::{info, warn};
 
 use crate::args::{Cli, Commands};
 use crate::db::{load_db, save_db};
-use crate::model::BooksDb;
+use crate::model::{BooksDb, OutputFormat};
+use crate::opf_edit::MetaEdit;
+use crate::report::{Changes, Report};
 
 pub fn run(cli: Cli) -> Result<()> {
     // Threading
@@ -29,44 +43,199 @@ pub fn run(cli: Cli) -> Result<()> {
     let mut db: BooksDb = load_db(&db_path).unwrap_or_default();
     info!("Loaded DB with {} record(s)", db.books.len());
 
-    match cli.cmd {
+    // With --output json or --dry-run, compare the entries around the run
+    let mut report = Report::new(cli.cmd.name(), cli.output == OutputFormat::Json);
+    report.dry_run = cli.dry_run;
+    if !report.json && !report.dry_run {
+        if dispatch(cli.cmd, &mut db, &db_path, &mut report, cli.dry_run)? {
+            save(&db_path, &db)?;
+        }
+        return Ok(());
+    }
+    let before = db.books.clone();
+    let roots_before = db.roots.clone();
+    let result =
+        dispatch(cli.cmd, &mut db, &db_path, &mut report, cli.dry_run).and_then(|changed| {
+            if changed && !cli.dry_run {
+                save(&db_path, &db)?;
+            }
+            Ok(())
+        });
+    let changes = Changes::between(&before, &db.books).with_roots(&roots_before, &db.roots);
+    if cli.dry_run && result.is_ok() {
+        info!(
+            "Dry run: {} added, {} changed, {} removed; DB not saved",
+            changes.added.len(),
+            changes.changed.len(),
+            changes.removed.len()
+        );
+        if report.json {
+            report.detail("diff", changes.to_json());
+        } else {
+            changes.print();
+        }
+    }
+    if report.json {
+        report.entries = db.books.len();
+        report.tally(&changes);
+        report.print(&result);
+    }
+    result
+}
+
+fn save(db_path: &Path, db: &BooksDb) -> Result<()> {
+    save_db(db_path, db)?;
+    info!("Saved DB to {}", db_path.to_string_lossy());
+    Ok(())
+}
+
+/// Run one subcommand; true if the DB should be saved.
+fn dispatch(
+    cmd: Commands,
+    db: &mut BooksDb,
+    db_path: &PathBuf,
+    report: &mut Report,
+    dry_run: bool,
+) -> Result<bool> {
+    let save = match cmd {
         Commands::Load {
             root,
-            follow_symlinks,
+            name,
+            scan,
             no_hash,
+            quick_hash,
+            descend_archives,
         } => {
-            load::cmd_load(&mut db, &db_path, root, follow_symlinks, no_hash)?;
-            save_db(&db_path, &db)?;
-            info!("Saved DB to {}", db_path.to_string_lossy());
+            let opts = load::LoadOpts {
+                no_hash,
+                quick_only: quick_hash,
+                descend_archives,
+            };
+            load::cmd_load(db, db_path, root, name, &scan, &opts)?;
+            true
         }
 
-        Commands::Check => {
-            check::cmd_check(&mut db)?;
-            save_db(&db_path, &db)?;
-            info!("Saved DB to {}", db_path.to_string_lossy());
+        Commands::Check { quick } => {
+            check::cmd_check(db, quick)?;
+            true
+        }
+
+        Commands::Watch {
+            roots,
+            scan,
+            no_hash,
+            debounce_ms,
+            save_interval,
+            save_after,
+        } => {
+            if dry_run {
+                bail!("watch has no dry run (it saves as it goes)");
+            }
+            let opts = watch::WatchOpts {
+                roots,
+                scan,
+                no_hash,
+                debounce: Duration::from_millis(debounce_ms),
+                save_interval: Duration::from_secs(save_interval),
+                save_after,
+            };
+            watch::cmd_watch(db, db_path, opts)?;
+            true
         }
 
         Commands::Prune => {
-            prune::cmd_prune(&mut db)?;
-            save_db(&db_path, &db)?;
-            info!("Saved DB to {}", db_path.to_string_lossy());
+            prune::cmd_prune(db)?;
+            true
         }
 
         Commands::Rehash { force } => {
-            rehash::cmd_rehash(&mut db, force)?;
-            save_db(&db_path, &db)?;
-            info!("Saved DB to {}", db_path.to_string_lossy());
+            rehash::cmd_rehash(db, force)?;
+            true
         }
 
         Commands::Merge { other } => {
-            merge::cmd_merge(&mut db, other)?;
-            save_db(&db_path, &db)?;
-            info!("Saved DB to {}", db_path.to_string_lossy());
+            merge::cmd_merge(db, other)?;
+            true
+        }
+
+        Commands::Diff { a, b, by } => {
+            diff::cmd_diff(&a, &b, by, report)?;
+            false
+        }
+
+        Commands::Validate { force, filter } => {
+            validate::cmd_validate(db, &filter, force, report)?;
+            true
+        }
+
+        Commands::Dedupe => {
+            dedupe::cmd_dedupe(db, report)?;
+            true
+        }
+
+        Commands::Root { action } => root::cmd_root(db, action, report)?,
+
+        Commands::Edit {
+            target,
+            title,
+            authors,
+            series,
+            series_index,
+            write_file,
+        } => {
+            if dry_run && write_file {
+                bail!(
+                    "edit --write-file has no dry run; drop --write-file to preview the DB change"
+                );
+            }
+            let edit = MetaEdit {
+                title,
+                authors,
+                series,
+                series_index,
+            };
+            edit::cmd_edit(db, &target, &edit, write_file)?;
+            true
+        }
+
+        Commands::Organize {
+            template,
+            dest,
+            copy,
+            filter,
+        } => {
+            let opts = organize::OrganizeOpts {
+                template,
+                dest,
+                copy,
+                dry_run,
+            };
+            organize::cmd_organize(db, db_path, &opts, &filter, report)?
+        }
+
+        Commands::Import { source } => {
+            import::cmd_import(db, source)?;
+            true
+        }
+
+        Commands::Export {
+            format,
+            fields,
+            out,
+            filter,
+        } => {
+            export::cmd_export(db, format, &fields, &filter, out, report)?;
+            false
+        }
+
+        Commands::Site { out, filter } => {
+            site::cmd_site(db, &out, &filter, report)?;
+            false
         }
 
         Commands::Count => {
-            count::cmd_count(&db)?;
-            // No save needed.
+            count::cmd_count(db, report)?;
+            false
         }
 
         Commands::Serve { backend } => {
@@ -74,16 +243,18 @@ pub fn run(cli: Cli) -> Result<()> {
                 "`serve` is not implemented yet (DNI). Backend arg = {:?}",
                 backend
             );
+            false
         }
 
         Commands::Query => {
             warn!("`query` is not implemented yet (DNI).");
+            false
         }
 
         Commands::Stow => {
             warn!("`stow` is not implemented yet (DNI). Will zpaq ultra-compress epubs.");
+            false
         }
-    }
-
-    Ok(())
+    };
+    Ok(save)
 }
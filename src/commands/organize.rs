@@ -0,0 +1,393 @@
+//! `organize`: move (or copy) books into a tree laid out by a path template.
+//!
+//! Template placeholders are `{field}` or `{field:0N}` (zero-pad numbers to N
+//! digits). Fields: `title`, `author`, `author_sort`, `publisher`,
+//! `publish_date`, `year`, `format`, `ext`, `stem`, `filename`, `id`, `drm`, and any
+//! `other_metadata` key (`series`, `series_index`, `language`, …).
+//! Path components that render empty are dropped, so books without a series
+//! land one level up. The DB entry is updated right after its file moves,
+//! and the DB is saved every `SAVE_EVERY` moves and when SIGINT/SIGTERM
+//! stops the run between two moves, so an interruption leaves at most a few
+//! moved files the DB does not know about yet.
+//! Books inside .zip/.tar bundles are left alone.
+
+use anyhow::{Result, bail};
+use serde_json::json;
+use std::collections::HashSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{debug, info, warn};
+
+use crate::archive;
+use crate::biblio::{author_names, sort_name};
+use crate::db::save_db;
+use crate::fb2;
+use crate::filter::EntryFilter;
+use crate::model::{BookEntry, BooksDb};
+use crate::remote;
+use crate::report::{self, Report};
+use crate::roots;
+use crate::util::{absolute, now_iso8601};
+use crate::volume::Volumes;
+
+/// Longest file name most filesystems accept, in bytes.
+const MAX_COMPONENT: usize = 255;
+
+/// Save the DB after this many moves.
+const SAVE_EVERY: usize = 20;
+
+#[derive(Debug, Clone)]
+pub struct OrganizeOpts {
+    pub template: String,
+    pub dest: PathBuf,
+    pub copy: bool,
+    /// Global `--dry-run`: print the plan and point the entries at their
+    /// planned paths in memory only, so the diff shows them
+    pub dry_run: bool,
+}
+
+/// One planned move; `dst` is already collision-free.
+struct Step {
+    idx: usize,
+    src: PathBuf,
+    dst: PathBuf,
+}
+
+/// Plan, then (unless `dry_run`) carry out the moves. Returns true if the DB changed.
+/// The steps (planned, or done) go under `details.steps` with `--output json`.
+pub fn cmd_organize(
+    db: &mut BooksDb,
+    db_path: &Path,
+    opts: &OrganizeOpts,
+    filter: &EntryFilter,
+    report: &mut Report,
+) -> Result<bool> {
+    let template = parse_template(&opts.template)?;
+    let dest = absolute(&opts.dest);
+
+    let mut taken: HashSet<PathBuf> = HashSet::new();
+    let mut plan = Vec::new();
+    let mut in_place = 0usize;
+    for (idx, e) in db.books.iter().enumerate() {
+        // Books inside bundles move with their bundle; exploded EPUB
+        // directories are working copies and stay put; remote books are
+        // not ours to move
+        if e.stale
+            || e.missing
+            || archive::is_member(e)
+            || remote::is_remote(e)
+            || !filter.matches(e)
+        {
+            continue;
+        }
+        let src = absolute(Path::new(&e.full_path));
+        if src.is_dir() {
+            continue;
+        }
+        let wanted = dest.join(render(&template, e));
+        let dst = free_path(&wanted, &src, &taken);
+        taken.insert(dst.clone());
+        if dst == src {
+            in_place += 1;
+            continue;
+        }
+        plan.push(Step { idx, src, dst });
+    }
+
+    let verb = if opts.copy { "copy" } else { "move" };
+    let step = |s: &Step| json!({"action": verb, "src": s.src, "dst": s.dst});
+    report.detail("in_place", in_place);
+    if opts.dry_run {
+        if report.json {
+            report.detail("steps", plan.iter().map(step).collect::<Vec<_>>());
+        } else {
+            for s in &plan {
+                println!("{}\t{}\t{}", verb, s.src.display(), s.dst.display());
+            }
+        }
+        info!(
+            "organize (dry run): {} file(s) to {}; {} already in place",
+            plan.len(),
+            verb,
+            in_place
+        );
+        for s in &plan {
+            let e = place(&mut db.books, s.idx, opts.copy);
+            roots::relocate(&db.roots, e, &s.dst);
+        }
+        return Ok(false);
+    }
+
+    // Ctrl-C / SIGTERM stop between two moves; the caller saves what was done
+    let stop = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&stop))?;
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&stop))?;
+
+    let mut vols = Volumes::new();
+    let (mut done, mut failed) = (0usize, 0usize);
+    let mut steps = Vec::new();
+    let total = plan.len();
+    for s in plan {
+        if stop.load(Ordering::Relaxed) {
+            warn!(
+                "organize: interrupted after {} of {} file(s)",
+                done + failed,
+                total
+            );
+            break;
+        }
+        if let Err(err) = transfer(&s.src, &s.dst, opts.copy) {
+            warn!("organize: {} {} failed: {}", verb, s.src.display(), err);
+            report::file_error(s.src.display(), format!("{verb} failed: {err}"));
+            failed += 1;
+            continue;
+        }
+        steps.push(step(&s));
+        let e = place(&mut db.books, s.idx, opts.copy);
+        roots::relocate(&db.roots, e, &s.dst);
+        e.volume = vols.volume_of(&s.dst);
+        debug!("organize: {} → {}", s.src.display(), s.dst.display());
+        done += 1;
+        if done % SAVE_EVERY == 0 {
+            save_db(db_path, db)?;
+            debug!("organize: saved DB after {} file(s)", done);
+        }
+    }
+    info!(
+        "organize summary → {}: {}; already in place: {}; failed: {}",
+        if opts.copy { "copied" } else { "moved" },
+        done,
+        in_place,
+        failed
+    );
+    report.detail("steps", steps);
+    Ok(done > 0)
+}
+
+/// The entry that will describe the file at its new place: the moved entry
+/// itself, or a fresh copy of it appended for `--copy`.
+fn place(books: &mut Vec<BookEntry>, idx: usize, copy: bool) -> &mut BookEntry {
+    if copy {
+        let mut copy = books[idx].clone();
+        copy.date_found = now_iso8601();
+        books.push(copy);
+        books.last_mut().expect("just pushed")
+    } else {
+        &mut books[idx]
+    }
+}
+
+#[derive(Debug)]
+enum Piece {
+    Text(String),
+    Field { name: String, pad: Option<usize> },
+}
+
+fn parse_template(t: &str) -> Result<Vec<Piece>> {
+    let mut out = Vec::new();
+    let mut rest = t;
+    while let Some(open) = rest.find('{') {
+        if open > 0 {
+            out.push(Piece::Text(rest[..open].to_string()));
+        }
+        let Some(close) = rest[open..].find('}') else {
+            bail!("unclosed '{{' in template {t:?}");
+        };
+        let spec = &rest[open + 1..open + close];
+        let (name, pad) = match spec.split_once(':') {
+            Some((name, fmt)) => {
+                let Some(width) = fmt.strip_prefix('0').and_then(|w| w.parse().ok()) else {
+                    bail!("unsupported format {fmt:?} in {{{spec}}} (only :0N zero padding)");
+                };
+                (name, Some(width))
+            }
+            None => (spec, None),
+        };
+        if name.is_empty() {
+            bail!("empty placeholder in template {t:?}");
+        }
+        out.push(Piece::Field {
+            name: name.to_string(),
+            pad,
+        });
+        rest = &rest[open + close + 1..];
+    }
+    if rest.contains('}') {
+        bail!("unmatched '}}' in template {t:?}");
+    }
+    if !rest.is_empty() {
+        out.push(Piece::Text(rest.to_string()));
+    }
+    Ok(out)
+}
+
+/// Relative target path for one entry: rendered, sanitized, empty parts dropped.
+fn render(template: &[Piece], e: &BookEntry) -> PathBuf {
+    let mut s = String::new();
+    for p in template {
+        match p {
+            Piece::Text(t) => s.push_str(t),
+            // Values must not introduce path separators of their own.
+            Piece::Field { name, pad } => {
+                s.push_str(&field(e, name, *pad).replace(['/', '\\'], "_"))
+            }
+        }
+    }
+    let parts: Vec<&str> = s.split('/').collect();
+    let last = parts.len() - 1;
+    parts
+        .iter()
+        .enumerate()
+        .map(|(i, c)| sanitize(c, i == last))
+        .filter(|c| !c.is_empty())
+        .collect()
+}
+
+fn field(e: &BookEntry, name: &str, pad: Option<usize>) -> String {
+    let path = Path::new(&e.filename);
+    let value = match name {
+        "title" => e.title.clone().or_else(|| stem(path)),
+        "author" => author_names(e).into_iter().next(),
+        "author_sort" => e
+            .other_metadata
+            .get("author_sort")
+            .cloned()
+            .or_else(|| author_names(e).first().map(|a| sort_name(a))),
+        "publisher" => e.publisher.clone(),
+        "publish_date" => e.publish_date.clone(),
+        "year" => e
+            .publish_date
+            .as_deref()
+            .and_then(|d| d.get(..4))
+            .filter(|y| y.chars().all(|c| c.is_ascii_digit()))
+            .map(str::to_string),
+        "format" => Some(e.format.as_str().to_string()),
+        // Double extension, so "{stem}.{ext}" round-trips
+        "ext" if fb2::is_fb2_zip(path) => Some("fb2.zip".into()),
+        "stem" if fb2::is_fb2_zip(path) => stem(&path.with_extension("")),
+        "ext" => path
+            .extension()
+            .map(|x| x.to_string_lossy().to_ascii_lowercase()),
+        "stem" => stem(path),
+        "filename" => Some(e.filename.clone()),
+        "id" => Some(e.id()),
+        "drm" => e.drm.map(|d| d.as_str().to_string()),
+        key => e.other_metadata.get(key).cloned(),
+    };
+    let value = value.unwrap_or_else(|| match name {
+        "author" | "author_sort" => "Unknown".into(),
+        _ => String::new(),
+    });
+    match pad {
+        Some(width) => zero_pad(value.trim(), width),
+        None => value.trim().to_string(),
+    }
+}
+
+fn stem(p: &Path) -> Option<String> {
+    p.file_stem().map(|s| s.to_string_lossy().to_string())
+}
+
+/// "1" → "01", "2.5" → "02.5"; non-numbers are left alone.
+fn zero_pad(v: &str, width: usize) -> String {
+    let (int, frac) = v.split_once('.').unwrap_or((v, ""));
+    if int.is_empty() || !int.chars().all(|c| c.is_ascii_digit()) {
+        return v.to_string();
+    }
+    // Calibre-style "1.0" is just 1
+    let frac = frac.trim_end_matches('0');
+    let int = format!("{int:0>width$}");
+    if frac.is_empty() {
+        int
+    } else {
+        format!("{int}.{frac}")
+    }
+}
+
+/// Make one path component safe on common filesystems (ext4, NTFS, exFAT, SMB):
+/// no reserved characters, no leading dot or separator debris, no trailing
+/// dot/space, no DOS device names, at most 255 bytes (keeping the extension).
+fn sanitize(c: &str, is_file: bool) -> String {
+    let mut s: String = c
+        .chars()
+        .map(|ch| match ch {
+            '<' | '>' | ':' | '"' | '\\' | '|' | '?' | '*' => '_',
+            ch if ch.is_control() => '_',
+            ch => ch,
+        })
+        .collect();
+    // Leftovers of empty fields, e.g. " - Title" when series_index is unset
+    s = s
+        .trim_start_matches(|ch: char| ch.is_whitespace() || matches!(ch, '-' | '–' | '_' | ','))
+        .trim_end_matches(|ch: char| ch.is_whitespace() || matches!(ch, '-' | '–' | '_' | ','))
+        .to_string();
+    if let Some(rest) = s.strip_prefix('.') {
+        s = format!("_{rest}");
+    }
+    while s.ends_with('.') || s.ends_with(' ') {
+        s.pop();
+    }
+    let stem = s.split('.').next().unwrap_or_default().to_ascii_uppercase();
+    let reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || ((stem.starts_with("COM") || stem.starts_with("LPT"))
+            && stem.len() == 4
+            && stem.as_bytes()[3].is_ascii_digit());
+    if reserved {
+        s.insert(0, '_');
+    }
+    if s.len() > MAX_COMPONENT {
+        let ext = if is_file {
+            Path::new(&s)
+                .extension()
+                .map(|x| format!(".{}", x.to_string_lossy()))
+                .filter(|x| x.len() < 16)
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+        let mut cut = MAX_COMPONENT - ext.len();
+        while !s.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        s = format!("{}{}", s[..cut].trim_end(), ext);
+    }
+    s
+}
+
+/// `wanted`, or `stem (2).ext`, `stem (3).ext`, … if it exists on disk or is
+/// already claimed by this run.
+fn free_path(wanted: &Path, src: &Path, taken: &HashSet<PathBuf>) -> PathBuf {
+    let clash = |p: &Path| taken.contains(p) || (p.exists() && p != src);
+    if !clash(wanted) {
+        return wanted.to_path_buf();
+    }
+    let stem = stem(wanted).unwrap_or_default();
+    let ext = wanted
+        .extension()
+        .map(|x| format!(".{}", x.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|n| wanted.with_file_name(format!("{stem} ({n}){ext}")))
+        .find(|p| !clash(p))
+        .expect("unbounded range")
+}
+
+/// Move (rename; copy + delete across filesystems) or copy one file.
+fn transfer(src: &Path, dst: &Path, copy: bool) -> std::io::Result<()> {
+    if let Some(dir) = dst.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if copy {
+        return fs::copy(src, dst).map(|_| ());
+    }
+    match fs::rename(src, dst) {
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            fs::copy(src, dst)?;
+            fs::remove_file(src)
+        }
+        r => r,
+    }
+}
@@ -1,13 +1,34 @@
 use crate::model::BooksDb;
 use anyhow::Result;
-use tracing::info;
+use tracing::{info, warn};
 
+use crate::volume::Volumes;
+
+/// Remove stale entries. Entries on a volume that is not mounted are kept:
+/// their files cannot be inspected, so we refuse to act on them.
 pub fn cmd_prune(db: &mut BooksDb) -> Result<()> {
     let before = db.books.len();
-    db.books.retain(|b| !b.stale);
+    let mut vols = Volumes::new();
+    let mut kept_offline = 0usize;
+    db.books.retain(|b| {
+        if !b.stale {
+            return true;
+        }
+        if vols.is_offline(b) {
+            kept_offline += 1;
+            return true;
+        }
+        false
+    });
     info!(
         "Pruned {} stale record(s)",
         before.saturating_sub(db.books.len())
     );
+    if kept_offline > 0 {
+        warn!(
+            "Kept {} stale record(s) on offline volume(s); mount them and prune again",
+            kept_offline
+        );
+    }
     Ok(())
 }
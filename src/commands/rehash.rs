@@ -1,17 +1,21 @@
 use anyhow::Result;
 use rayon::prelude::*;
-use std::fs;
 use std::path::PathBuf;
 use tracing::{info, warn};
 
+use crate::archive::{self, Members};
+use crate::container;
 use crate::hash;
-use crate::model::BooksDb;
+use crate::model::{BookEntry, BooksDb, FileFormat};
+use crate::remote;
+use crate::report;
 
-/// Fill in missing hashes (xxhash == None) for entries whose files exist.
+/// Fill in missing hashes (xxhash, quick_hash, or EPUB content_hash == None)
+/// for entries whose files exist. Remote files are downloaded once each.
 /// If `force` is true, recompute all hashes for existing files.
 pub fn cmd_rehash(db: &mut BooksDb, force: bool) -> Result<()> {
     // Gather candidate indices + paths to avoid borrowing issues
-    let candidates: Vec<(usize, PathBuf)> = db
+    let candidates: Vec<(usize, PathBuf, FileFormat, Option<&BookEntry>)> = db
         .books
         .iter()
         .enumerate()
@@ -21,13 +25,16 @@ pub fn cmd_rehash(db: &mut BooksDb, force: bool) -> Result<()> {
                 return None;
             }
             let path = PathBuf::from(&b.full_path);
-            if !path.exists() {
+            let remote = remote::is_remote(b);
+            if (remote && b.missing) || (!remote && !archive::disk_path(b).exists()) {
                 // keep DB flag truthful; don't try to hash missing files
                 // (do not modify here; a future `check` will mark missing if needed)
                 return None;
             }
-            if force || b.xxhash.is_none() {
-                Some((i, path))
+            let needs_content = b.format.has_content_hash() && b.content_hash.is_none();
+            if force || b.xxhash.is_none() || b.quick_hash.is_none() || needs_content {
+                let elsewhere = archive::is_member(b) || remote;
+                Some((i, path, b.format, elsewhere.then_some(b)))
             } else {
                 None
             }
@@ -45,29 +52,86 @@ pub fn cmd_rehash(db: &mut BooksDb, force: bool) -> Result<()> {
         if force { " (force mode)" } else { "" }
     );
 
+    // Each tarball is decompressed once for all of its members
+    let members = Members::new(candidates.iter().filter_map(|c| c.3));
+
     // Hash in parallel; also fetch up-to-date size while we're at it
+    // (`None` when the file could not be read: the recorded size stays)
     #[derive(Debug)]
     struct RehashOut {
         idx: usize,
-        size_bytes: u64,
+        size_bytes: Option<u64>,
         hash: Option<u128>,
+        content_hash: Option<u128>,
+        quick_hash: Option<u128>,
     }
 
     let results: Vec<RehashOut> = candidates
         .into_par_iter()
-        .map(|(idx, path)| {
-            let size_bytes = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        .map(|(idx, path, format, elsewhere)| {
+            // Remote files: one download, all hashes from the copy
+            if let Some(e) = elsewhere.filter(|e| remote::is_remote(e)) {
+                let fetched =
+                    remote::locate(e).and_then(|(src, p)| remote::fetch(src.as_ref(), &p, format));
+                return match fetched {
+                    Ok(got) => RehashOut {
+                        idx,
+                        size_bytes: Some(got.size),
+                        hash: Some(got.xxhash),
+                        content_hash: got.content_hash,
+                        quick_hash: got.quick_hash,
+                    },
+                    Err(err) => {
+                        warn!("rehash: failed to fetch {}: {:#}", e.full_path, err);
+                        report::file_error(&e.full_path, &err);
+                        RehashOut {
+                            idx,
+                            size_bytes: None,
+                            hash: None,
+                            content_hash: None,
+                            quick_hash: None,
+                        }
+                    }
+                };
+            }
+            // Books inside a bundle: hash the member's bytes
+            if let Some(e) = elsewhere {
+                let (hash, quick_hash, content_hash, size_bytes) = match members.read(e) {
+                    Ok(m) => {
+                        let (hash, quick, content) = m.hashes(format, false, false);
+                        (hash, quick, content, Some(m.size))
+                    }
+                    Err(err) => {
+                        warn!("rehash: failed to read {}: {:#}", e.full_path, err);
+                        report::file_error(&e.full_path, &err);
+                        (None, None, None, None)
+                    }
+                };
+                return RehashOut {
+                    idx,
+                    size_bytes,
+                    hash,
+                    content_hash,
+                    quick_hash,
+                };
+            }
             let hash = match hash::xxh3_file(&path) {
                 Ok(h) => Some(h),
                 Err(e) => {
                     warn!("rehash: failed to hash {}: {}", path.display(), e);
+                    report::file_error(path.display(), &e);
                     None
                 }
             };
+            let size_bytes = hash.map(|_| container::book_size(&path));
+            let content_hash = hash::content_hash(&path, format);
+            let quick_hash = hash::quick_hash(&path).ok();
             RehashOut {
                 idx,
                 size_bytes,
                 hash,
+                content_hash,
+                quick_hash,
             }
         })
         .collect();
@@ -76,11 +140,15 @@ pub fn cmd_rehash(db: &mut BooksDb, force: bool) -> Result<()> {
     let mut updated = 0usize;
     for r in results {
         if let Some(entry) = db.books.get_mut(r.idx) {
-            // Update size to current value (cheap & useful)
-            entry.size_bytes = r.size_bytes;
+            // Update size to current value (cheap & useful), if it was read
+            if let Some(size) = r.size_bytes {
+                entry.size_bytes = size;
+            }
             // Only write hash if we got one; otherwise leave as-is
             if r.hash.is_some() {
                 entry.xxhash = r.hash;
+                entry.content_hash = r.content_hash;
+                entry.quick_hash = r.quick_hash;
                 updated += 1;
             }
         }
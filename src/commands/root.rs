@@ -0,0 +1,56 @@
+use anyhow::{Result, bail};
+use serde_json::json;
+use tracing::info;
+
+use crate::args::RootAction;
+use crate::model::BooksDb;
+use crate::report::Report;
+use crate::roots;
+
+/// List / set / remove named roots. Returns true if the DB needs saving.
+/// With `--output json` the listing goes under `details.roots`.
+pub fn cmd_root(db: &mut BooksDb, action: RootAction, report: &mut Report) -> Result<bool> {
+    match action {
+        RootAction::List => {
+            let mut listed = Vec::new();
+            for (name, dir) in &db.roots {
+                let n = db
+                    .books
+                    .iter()
+                    .filter(|b| b.root.as_deref() == Some(name.as_str()))
+                    .count();
+                if report.json {
+                    listed.push(json!({
+                        "name": name,
+                        "dir": dir,
+                        "entries": n,
+                        "present": dir.is_dir(),
+                    }));
+                    continue;
+                }
+                let state = if dir.is_dir() { "" } else { " (not present)" };
+                println!("{}\t{}\t{}{}", name, dir.display(), n, state);
+            }
+            report.detail("roots", listed);
+            Ok(false)
+        }
+        RootAction::Set { name, dir } => roots::set_root(db, &name, &dir),
+        RootAction::Remove { name } => {
+            if db.roots.remove(&name).is_none() {
+                bail!("unknown root {name:?}");
+            }
+            let mut n = 0usize;
+            for b in db
+                .books
+                .iter_mut()
+                .filter(|b| b.root.as_deref() == Some(name.as_str()))
+            {
+                b.root = None;
+                b.rel_path = None;
+                n += 1;
+            }
+            info!("root {}: removed ({} entr(y/ies) detached)", name, n);
+            Ok(true)
+        }
+    }
+}
@@ -0,0 +1,444 @@
+//! `site`: render a static, self-contained HTML catalog.
+//!
+//! Layout under `--out`:
+//! - index.html, style.css
+//! - authors/index.html, authors/<slug>.html
+//! - series/index.html, series/<slug>.html
+//! - books/<id>.html (id = `BookEntry::id`), covers/<id>.<ext> (scaled down
+//!   to `THUMB_WIDTH`)
+//!
+//! Links to book files are relative to the page, so the catalog keeps working
+//! when the catalog and the library are moved together (USB stick, share).
+
+use anyhow::{Context, Result};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use percent_encoding::{AsciiSet, CONTROLS, utf8_percent_encode};
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use tracing::{debug, info};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::archive::{self, Members};
+use crate::biblio::author_names;
+use crate::filter::EntryFilter;
+use crate::metadata;
+use crate::model::{BookEntry, BooksDb};
+use crate::remote::{self, RangeReader};
+use crate::report::Report;
+use crate::util::{absolute, relative_path};
+
+/// Width covers are scaled down to, in pixels.
+const THUMB_WIDTH: u32 = 300;
+
+/// Characters escaped in relative file links (path separators are kept).
+const LINK_ESCAPE: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+const STYLE: &str = "\
+body{font-family:system-ui,sans-serif;max-width:60rem;margin:0 auto;padding:1rem;color:#222}
+nav a{margin-right:1rem}
+ul.books{list-style:none;padding:0}
+ul.books li{padding:.3rem 0;border-bottom:1px solid #eee}
+.meta{color:#666;font-size:.9em}
+.book{display:flex;gap:1.5rem;align-items:flex-start}
+.book img{max-width:200px;max-height:300px;box-shadow:0 1px 4px #0003}
+dl{display:grid;grid-template-columns:max-content auto;gap:.2rem 1rem}
+dt{font-weight:600}
+";
+
+pub fn cmd_site(db: &BooksDb, out: &Path, filter: &EntryFilter, report: &mut Report) -> Result<()> {
+    let mut books: Vec<&BookEntry> = db.books.iter().filter(|e| filter.matches(e)).collect();
+    books.sort_by_key(|e| sort_title(e));
+
+    for sub in ["", "books", "authors", "series", "covers"] {
+        fs::create_dir_all(out.join(sub))
+            .with_context(|| format!("create {}", out.join(sub).display()))?;
+    }
+    let out = absolute(out);
+    fs::write(out.join("style.css"), STYLE)?;
+
+    // Covers (zip reads) in parallel; None when a book has no usable cover
+    let members = Members::new(books.iter().copied());
+    let covers: Vec<Option<String>> = books
+        .par_iter()
+        .map(|e| write_cover(e, &out, &members))
+        .collect();
+
+    let mut by_author: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    let mut by_series: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, e) in books.iter().enumerate() {
+        for a in author_names(e) {
+            by_author.entry(a).or_default().push(i);
+        }
+        if let Some(s) = e.other_metadata.get("series") {
+            by_series.entry(s.clone()).or_default().push(i);
+        }
+    }
+    for idxs in by_series.values_mut() {
+        idxs.sort_by(|&a, &b| series_index(books[a]).total_cmp(&series_index(books[b])));
+    }
+
+    // Book pages
+    for (e, cover) in books.iter().zip(&covers) {
+        let page = book_page(e, cover.as_deref(), &out);
+        fs::write(out.join("books").join(format!("{}.html", e.id())), page)?;
+    }
+
+    // Author / series pages + their indexes
+    for (kind, groups) in [("authors", &by_author), ("series", &by_series)] {
+        let mut items = String::new();
+        for (name, idxs) in groups {
+            let slug = slug(name);
+            items.push_str(&format!(
+                "<li><a href=\"{}.html\">{}</a> <span class=\"meta\">({})</span></li>\n",
+                slug,
+                esc(name),
+                idxs.len()
+            ));
+            let list = book_list(
+                idxs.iter().map(|&i| books[i]),
+                "../books/",
+                kind == "series",
+            );
+            let body = format!("<h1>{}</h1>\n{}", esc(name), list);
+            fs::write(
+                out.join(kind).join(format!("{slug}.html")),
+                page(name, 1, &body),
+            )?;
+        }
+        let title = if kind == "authors" {
+            "Authors"
+        } else {
+            "Series"
+        };
+        let body = format!("<h1>{title}</h1>\n<ul>\n{items}</ul>");
+        fs::write(out.join(kind).join("index.html"), page(title, 1, &body))?;
+    }
+
+    let body = format!(
+        "<h1>Catalog</h1>\n<p class=\"meta\">{} book(s), {} author(s), {} series</p>\n{}",
+        books.len(),
+        by_author.len(),
+        by_series.len(),
+        book_list(books.iter().copied(), "books/", false)
+    );
+    fs::write(out.join("index.html"), page("Catalog", 0, &body))?;
+
+    info!(
+        "site: {} book page(s), {} author(s), {} series, {} cover(s) → {}",
+        books.len(),
+        by_author.len(),
+        by_series.len(),
+        covers.iter().flatten().count(),
+        out.display()
+    );
+    report.detail("out", out);
+    report.detail("books", books.len());
+    report.detail("authors", by_author.len());
+    report.detail("series", by_series.len());
+    report.detail("covers", covers.iter().flatten().count());
+    Ok(())
+}
+
+/// ASCII slug plus a short hash, so non-Latin and near-identical names stay distinct.
+fn slug(name: &str) -> String {
+    let mut s = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            s.push(c.to_ascii_lowercase());
+        } else if !s.ends_with('-') && !s.is_empty() {
+            s.push('-');
+        }
+    }
+    let s = s.trim_end_matches('-');
+    let h = xxh3_64(name.as_bytes()) & 0xffff_ffff;
+    if s.is_empty() {
+        format!("{h:08x}")
+    } else {
+        format!("{s}-{h:08x}")
+    }
+}
+
+fn display_title(e: &BookEntry) -> &str {
+    e.title.as_deref().unwrap_or(&e.filename)
+}
+
+fn sort_title(e: &BookEntry) -> String {
+    display_title(e).to_lowercase()
+}
+
+fn series_index(e: &BookEntry) -> f64 {
+    e.other_metadata
+        .get("series_index")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(f64::MAX)
+}
+
+fn esc(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Resolve character references (`&amp;`, `&#8217;`, `&#x2019;` and the
+/// common named ones) left in text once `strip_tags` removed the markup, so
+/// `esc` does not escape them twice. Unknown references are kept as written.
+fn decode_entities(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let decoded = rest[1..].find(';').filter(|&n| n <= 10).and_then(|n| {
+            let name = &rest[1..1 + n];
+            let c = match name {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => '\u{a0}',
+                _ => {
+                    let code = match name.strip_prefix('#')? {
+                        hex if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16),
+                        dec => dec.parse(),
+                    };
+                    char::from_u32(code.ok()?)?
+                }
+            };
+            Some((c, n + 2))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// OPF descriptions are often HTML fragments; keep the text only.
+fn strip_tags(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut in_tag = false;
+    for c in s.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                out.push(' ');
+            }
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Wrap a body in the common page shell; `depth` = directories below the site root.
+fn page(title: &str, depth: usize, body: &str) -> String {
+    let up = "../".repeat(depth);
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <title>{}</title><link rel=\"stylesheet\" href=\"{up}style.css\"></head>\n<body>\n\
+         <nav><a href=\"{up}index.html\">All books</a><a href=\"{up}authors/index.html\">Authors</a>\
+         <a href=\"{up}series/index.html\">Series</a></nav>\n{}\n</body></html>\n",
+        esc(title),
+        body
+    )
+}
+
+fn book_list<'a>(
+    books: impl Iterator<Item = &'a BookEntry>,
+    prefix: &str,
+    with_index: bool,
+) -> String {
+    let mut s = String::from("<ul class=\"books\">\n");
+    for e in books {
+        let idx = e
+            .other_metadata
+            .get("series_index")
+            .filter(|_| with_index)
+            .map(|i| format!("{} · ", esc(i)))
+            .unwrap_or_default();
+        s.push_str(&format!(
+            "<li>{}<a href=\"{}{}.html\">{}</a> <span class=\"meta\">{}</span></li>\n",
+            idx,
+            prefix,
+            e.id(),
+            esc(display_title(e)),
+            esc(&author_names(e).join(", "))
+        ));
+    }
+    s.push_str("</ul>");
+    s
+}
+
+fn book_page(e: &BookEntry, cover: Option<&str>, out: &Path) -> String {
+    let mut body = format!("<h1>{}</h1>\n<div class=\"book\">\n", esc(display_title(e)));
+    if let Some(c) = cover {
+        body.push_str(&format!("<img src=\"../{}\" alt=\"cover\">\n", esc(c)));
+    }
+
+    let mut rows: Vec<(&str, String)> = Vec::new();
+    let authors: Vec<String> = author_names(e)
+        .iter()
+        .map(|a| format!("<a href=\"../authors/{}.html\">{}</a>", slug(a), esc(a)))
+        .collect();
+    if !authors.is_empty() {
+        rows.push(("Author", authors.join(", ")));
+    }
+    if let Some(s) = e.other_metadata.get("series") {
+        let idx = e
+            .other_metadata
+            .get("series_index")
+            .map(|i| format!(" #{}", esc(i)))
+            .unwrap_or_default();
+        rows.push((
+            "Series",
+            format!(
+                "<a href=\"../series/{}.html\">{}</a>{}",
+                slug(s),
+                esc(s),
+                idx
+            ),
+        ));
+    }
+    for (label, v) in [
+        ("Publisher", e.publisher.as_ref()),
+        ("Published", e.publish_date.as_ref()),
+        ("Language", e.other_metadata.get("language")),
+        ("Identifier", e.other_metadata.get("identifier")),
+    ] {
+        if let Some(v) = v {
+            rows.push((label, esc(v)));
+        }
+    }
+    rows.push((
+        "File",
+        format!(
+            "<a href=\"{}\">{}</a> <span class=\"meta\">{}, {}</span>",
+            file_link(e, &out.join("books")),
+            esc(&e.filename),
+            e.format.as_str().to_uppercase(),
+            humansize::format_size(e.size_bytes, humansize::DECIMAL)
+        ),
+    ));
+
+    body.push_str("<dl>\n");
+    for (k, v) in rows {
+        body.push_str(&format!("<dt>{k}</dt><dd>{v}</dd>\n"));
+    }
+    body.push_str("</dl>\n</div>\n");
+
+    if let Some(d) = &e.description {
+        body.push_str(&format!(
+            "<h2>Description</h2>\n<p>{}</p>\n",
+            esc(&decode_entities(&strip_tags(d)))
+        ));
+    }
+    if !e.chapters.is_empty() {
+        body.push_str("<h2>Chapters</h2>\n<ol>\n");
+        for c in &e.chapters {
+            body.push_str(&format!("<li>{}</li>\n", esc(c)));
+        }
+        body.push_str("</ol>\n");
+    }
+    page(display_title(e), 1, &body)
+}
+
+/// Link from `page_dir` to the book file (the bundle, for archive members),
+/// relative so the catalog is portable. Remote files link to the server.
+fn file_link(e: &BookEntry, page_dir: &Path) -> String {
+    if remote::is_remote(e) {
+        return esc(&remote::web_url(&e.uri_path));
+    }
+    let rel = relative_path(page_dir, &absolute(&archive::disk_path(e)));
+    let rel = rel
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join("/");
+    esc(&utf8_percent_encode(&rel, LINK_ESCAPE).to_string())
+}
+
+/// Copy the book's cover to covers/<id>.<ext>; returns the site-relative path.
+fn write_cover(e: &BookEntry, out: &Path, members: &Members) -> Option<String> {
+    if e.missing {
+        return None;
+    }
+    let cover = if remote::is_remote(e) {
+        RangeReader::for_entry(e)
+            .and_then(|r| metadata::read_cover(r, Path::new(&e.filename), e.format))
+    } else if archive::is_member(e) {
+        members
+            .read(e)
+            .and_then(|m| metadata::read_cover(m.reader()?, Path::new(&e.filename), e.format))
+    } else {
+        metadata::extract_cover(Path::new(&e.full_path), e.format)
+    };
+    let (bytes, media_type) = match cover {
+        Ok(Some(c)) => c,
+        Ok(None) => return None,
+        Err(err) => {
+            debug!("site: no cover for {}: {}", e.full_path, err);
+            return None;
+        }
+    };
+    let ext = match media_type.as_str() {
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/svg+xml" => "svg",
+        "image/webp" => "webp",
+        _ => "jpg",
+    };
+    let (bytes, ext) = thumbnail(bytes, ext);
+    let rel = format!("covers/{}.{}", e.id(), ext);
+    fs::write(out.join(&rel), bytes).ok()?;
+    Some(rel)
+}
+
+/// A cover wider than `THUMB_WIDTH`, re-encoded at that width: JPEG, or PNG
+/// when it has transparency. Narrower images and ones that do not decode
+/// (SVG, damaged files) are kept as they are.
+fn thumbnail(bytes: Vec<u8>, ext: &'static str) -> (Vec<u8>, &'static str) {
+    let img = match image::load_from_memory(&bytes) {
+        Ok(img) if img.width() > THUMB_WIDTH => img,
+        _ => return (bytes, ext),
+    };
+    let img = img.resize(THUMB_WIDTH, u32::MAX, FilterType::Triangle);
+    let (img, format, thumb_ext) = if img.color().has_alpha() {
+        (DynamicImage::from(img.to_rgba8()), ImageFormat::Png, "png")
+    } else {
+        (DynamicImage::from(img.to_rgb8()), ImageFormat::Jpeg, "jpg")
+    };
+    let mut buf = Cursor::new(Vec::new());
+    match img.write_to(&mut buf, format) {
+        Ok(()) => (buf.into_inner(), thumb_ext),
+        Err(err) => {
+            debug!("site: cover not scaled: {}", err);
+            (bytes, ext)
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use serde_json::json;
+use std::fs::File;
+use std::path::Path;
+use tracing::{debug, info, warn};
+
+use crate::archive::{self, Members};
+use crate::filter::EntryFilter;
+use crate::model::{BookEntry, BooksDb, FileFormat, ValidationStatus};
+use crate::remote::{self, RangeReader};
+use crate::report::{self, Report};
+use crate::validate::{validate_epub, validate_with};
+use crate::volume::Volumes;
+
+/// Run the structural validator over EPUB entries selected by `filter` that
+/// are present on disk, storing status + issues on each entry. Entries that
+/// already have a result are skipped unless `force` (a content change creates
+/// a fresh, unvalidated record, and a re-zip of the same content clears the
+/// result in place, so both are picked up). Problem books are listed on stdout
+/// (under `details.problems` with `--output json`). Files that are gone or on
+/// an offline volume are skipped, and files that cannot be opened or read are
+/// reported as errors with their stored result left as it was.
+pub fn cmd_validate(
+    db: &mut BooksDb,
+    filter: &EntryFilter,
+    force: bool,
+    report: &mut Report,
+) -> Result<()> {
+    let mut vols = Volumes::new();
+    let todo: Vec<usize> = db
+        .books
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| e.format == FileFormat::Epub && !e.missing && filter.matches(e))
+        .filter(|(_, e)| force || e.validation.is_none())
+        .filter(|(_, e)| {
+            if remote::is_remote(e) || archive::disk_path(e).exists() {
+                return true;
+            }
+            if vols.is_offline(e) {
+                debug!("Volume offline, skipped: {}", e.full_path);
+            } else {
+                debug!("Not found, skipped: {}", e.full_path);
+            }
+            false
+        })
+        .map(|(i, _)| i)
+        .collect();
+    info!("validate: {} EPUB(s) to check", todo.len());
+
+    let members = Members::new(todo.iter().map(|&i| &db.books[i]));
+    let results: Vec<_> = todo
+        .par_iter()
+        .filter_map(|&i| {
+            let e = &db.books[i];
+            match open_and_validate(e, &members) {
+                Ok(result) => Some((i, result)),
+                Err(err) => {
+                    warn!("validate: failed to read {}: {:#}", e.full_path, err);
+                    report::file_error(&e.full_path, &err);
+                    None
+                }
+            }
+        })
+        .collect();
+
+    let (mut valid, mut warnings, mut invalid) = (0usize, 0usize, 0usize);
+    let mut problems = Vec::new();
+    for (i, (status, issues)) in results {
+        let e = &mut db.books[i];
+        match status {
+            ValidationStatus::Valid => valid += 1,
+            ValidationStatus::Warnings => warnings += 1,
+            ValidationStatus::Invalid => invalid += 1,
+        }
+        if status != ValidationStatus::Valid && report.json {
+            problems.push(json!({
+                "path": e.full_path,
+                "status": status.as_str(),
+                "issues": issues,
+            }));
+        } else if status != ValidationStatus::Valid {
+            println!("{}\t{}", status.as_str(), e.full_path);
+            for issue in &issues {
+                println!("\t{issue}");
+            }
+        }
+        e.validation = Some(status);
+        e.validation_issues = issues;
+    }
+    info!(
+        "validate summary → valid: {}; warnings: {}; invalid: {}",
+        valid, warnings, invalid
+    );
+    report.detail("valid", valid);
+    report.detail("warnings", warnings);
+    report.detail("invalid", invalid);
+    report.detail("problems", problems);
+    Ok(())
+}
+
+/// Open `e` and validate it. Failing to open or read the file is an error
+/// rather than a validation result; anything wrong inside it is an issue.
+fn open_and_validate(e: &BookEntry, members: &Members) -> Result<(ValidationStatus, Vec<String>)> {
+    if remote::is_remote(e) {
+        let r = RangeReader::for_entry(e)?;
+        return Ok(validate_with(|| Ok(r)));
+    }
+    if archive::is_member(e) {
+        let m = members.read(e)?;
+        let r = m.reader()?;
+        return Ok(validate_with(|| Ok(r)));
+    }
+    let path = Path::new(&e.full_path);
+    if path.is_dir() {
+        return Ok(validate_epub(path));
+    }
+    let f = File::open(path).with_context(|| format!("open {}", path.display()))?;
+    Ok(validate_with(|| Ok(f)))
+}
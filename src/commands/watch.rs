@@ -0,0 +1,308 @@
+//! `watch`: reconcile once, then keep the DB live from filesystem events.
+//!
+//! Events are queued and applied after `debounce` of quiet time, using the same
+//! per-file logic as `load` (`build_entry` + `merge_entry`) and `check`
+//! (`check_entry`). The DB is saved once `save_after` changes have piled up or
+//! `save_interval` has elapsed with unsaved changes, and on SIGINT/SIGTERM.
+//! A change to any file inside an exploded EPUB directory re-checks that book.
+
+use anyhow::{Context, Result};
+use notify::event::{AccessKind, AccessMode, ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{RecvTimeoutError, channel};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::archive::Members;
+use crate::commands::check::{check_entry, check_roots};
+use crate::commands::common::merge_entry;
+use crate::commands::load::{LoadOpts, build_entry, load_dir};
+use crate::container::is_exploded_epub;
+use crate::db::save_db;
+use crate::model::BooksDb;
+use crate::roots;
+use crate::scan::{IGNORE_FILENAME, PathFilter, ScanOpts, gather_epubs, is_book_path};
+use crate::volume::Volumes;
+
+#[derive(Debug, Clone)]
+pub struct WatchOpts {
+    pub roots: Vec<PathBuf>,
+    pub scan: ScanOpts,
+    pub no_hash: bool,
+    pub debounce: Duration,
+    pub save_interval: Duration,
+    pub save_after: usize,
+}
+
+/// One queued filesystem change, applied in arrival order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Change {
+    Upsert(PathBuf),
+    Remove(PathBuf),
+    Move(PathBuf, PathBuf),
+}
+
+impl Change {
+    /// Where the change lands (the destination, for a move).
+    fn target(&self) -> &Path {
+        match self {
+            Change::Upsert(p) | Change::Remove(p) | Change::Move(_, p) => p,
+        }
+    }
+
+    /// Whether an `.epubrignore` file was created, edited, moved or removed.
+    fn touches_ignore_file(&self) -> bool {
+        let is_ignore = |p: &Path| p.file_name().is_some_and(|n| n == IGNORE_FILENAME);
+        match self {
+            Change::Move(from, to) => is_ignore(from) || is_ignore(to),
+            c => is_ignore(c.target()),
+        }
+    }
+}
+
+/// One filter per watched root; built again when an ignore file changes.
+fn build_filters(opts: &WatchOpts) -> Result<Vec<PathFilter>> {
+    opts.roots
+        .iter()
+        .map(|r| PathFilter::new(r, &opts.scan))
+        .collect()
+}
+
+/// The exploded EPUB directory `p` lies inside, if any.
+fn exploded_owner(p: &Path) -> Option<PathBuf> {
+    p.ancestors()
+        .skip(1)
+        .find(|a| is_exploded_epub(a))
+        .map(Path::to_path_buf)
+}
+
+pub fn cmd_watch(db: &mut BooksDb, db_path: &PathBuf, mut opts: WatchOpts) -> Result<()> {
+    // Event paths are absolute; scan the same way so both agree on `full_path`.
+    for root in opts.roots.iter_mut() {
+        *root = root
+            .canonicalize()
+            .with_context(|| format!("resolve {}", root.display()))?;
+    }
+
+    let mut filters = build_filters(&opts)?;
+
+    // 1) Initial reconcile: pick up new files, then verify what we already know.
+    for root in &opts.roots {
+        let load = LoadOpts {
+            no_hash: opts.no_hash,
+            ..LoadOpts::default()
+        };
+        load_dir(db, root, &opts.scan, &load)?;
+    }
+    check_roots(db, true, &opts.roots)?;
+    save_db(db_path, db)?;
+    info!("watch: initial reconcile saved to {}", db_path.display());
+
+    // 2) Subscribe to events
+    let (tx, rx) = channel();
+    let mut watcher = notify::recommended_watcher(tx).context("create filesystem watcher")?;
+    for root in &opts.roots {
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .with_context(|| format!("watch {}", root.display()))?;
+        info!("watch: watching {}", root.display());
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&stop))?;
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&stop))?;
+
+    let mut pending: Vec<Change> = Vec::new();
+    let mut last_event = Instant::now();
+    let mut last_save = Instant::now();
+    let mut dirty = 0usize;
+
+    while !stop.load(Ordering::Relaxed) {
+        match rx.recv_timeout(Duration::from_millis(250)) {
+            Ok(Ok(ev)) => {
+                queue_event(&mut pending, ev);
+                last_event = Instant::now();
+            }
+            Ok(Err(e)) => warn!("watch: event error: {}", e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if !pending.is_empty() && last_event.elapsed() >= opts.debounce {
+            if pending.iter().any(Change::touches_ignore_file) {
+                filters = build_filters(&opts)?;
+                info!("watch: {} changed, rules reloaded", IGNORE_FILENAME);
+            }
+            dirty += apply_changes(db, std::mem::take(&mut pending), &opts, &filters);
+        }
+        if dirty > 0 && (dirty >= opts.save_after || last_save.elapsed() >= opts.save_interval) {
+            save_db(db_path, db)?;
+            info!("watch: saved {} change(s) to {}", dirty, db_path.display());
+            dirty = 0;
+            last_save = Instant::now();
+        }
+    }
+
+    // Flush whatever is still queued; the caller does the final save.
+    apply_changes(db, pending, &opts, &filters);
+    info!("watch: stopping");
+    Ok(())
+}
+
+fn queue_event(pending: &mut Vec<Change>, ev: Event) {
+    debug!("watch: event {:?} {:?}", ev.kind, ev.paths);
+    let mut push = |c: Change| {
+        // Collapse bursts of identical events (e.g. many writes to one file)
+        if pending.last() != Some(&c) {
+            pending.push(c);
+        }
+    };
+    match ev.kind {
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if ev.paths.len() == 2 => {
+            // The backend already reported the two halves (From, To); a paired
+            // rename supersedes them so the entry is moved, not re-indexed.
+            let (from, to) = (ev.paths[0].clone(), ev.paths[1].clone());
+            pending
+                .retain(|c| *c != Change::Remove(from.clone()) && *c != Change::Upsert(to.clone()));
+            pending.push(Change::Move(from, to));
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) | EventKind::Remove(_) => {
+            ev.paths.into_iter().for_each(|p| push(Change::Remove(p)));
+        }
+        EventKind::Create(_)
+        | EventKind::Modify(ModifyKind::Name(_))
+        | EventKind::Modify(ModifyKind::Data(_))
+        | EventKind::Modify(ModifyKind::Any)
+        | EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
+            ev.paths.into_iter().for_each(|p| push(Change::Upsert(p)));
+        }
+        _ => {}
+    }
+}
+
+/// Apply queued changes in order; returns how many DB edits were made.
+fn apply_changes(
+    db: &mut BooksDb,
+    changes: Vec<Change>,
+    opts: &WatchOpts,
+    filters: &[PathFilter],
+) -> usize {
+    let admitted = |p: &Path| filters.iter().any(|f| f.admits(p));
+    // Globs and depth are root-relative, so walk new directories unfiltered
+    // and apply the root's rules to each file found.
+    let dir_walk = ScanOpts {
+        follow_symlinks: opts.scan.follow_symlinks,
+        skip_hidden: opts.scan.skip_hidden,
+        one_file_system: opts.scan.one_file_system,
+        ..ScanOpts::default()
+    };
+
+    let mut n = 0;
+    let mut rechecked: HashSet<PathBuf> = HashSet::new();
+    for c in changes {
+        // Edits inside an exploded book re-check the book, once per batch
+        let c = match exploded_owner(c.target()) {
+            Some(book) if !rechecked.insert(book.clone()) => continue,
+            Some(book) => Change::Upsert(book),
+            None => c,
+        };
+        n += match c {
+            Change::Upsert(p) if p.is_dir() => gather_epubs(&p, &dir_walk)
+                .unwrap_or_default()
+                .iter()
+                .filter(|f| admitted(f))
+                .map(|f| upsert(db, f, opts.no_hash))
+                .sum(),
+            Change::Upsert(p) if admitted(&p) => upsert(db, &p, opts.no_hash),
+            Change::Upsert(_) => 0,
+            Change::Remove(p) => mark_missing(db, &p),
+            Change::Move(from, to) => {
+                let moved = move_entries(db, &from, &to, &admitted);
+                if moved > 0 {
+                    moved
+                } else {
+                    // Moved in from an unknown name: index it as new.
+                    apply_changes(db, vec![Change::Upsert(to)], opts, filters)
+                }
+            }
+        };
+    }
+    n
+}
+
+/// Insert or re-check a single file (or exploded EPUB directory).
+fn upsert(db: &mut BooksDb, p: &Path, no_hash: bool) -> usize {
+    let book = if p.is_dir() {
+        is_exploded_epub(p)
+    } else {
+        p.is_file() && is_book_path(p)
+    };
+    if !book {
+        return 0;
+    }
+    let key = p.to_string_lossy();
+    if let Some(existing) = db.books.iter_mut().find(|b| b.full_path == key && !b.stale) {
+        existing.missing = false;
+        if let Some(fresh) = check_entry(existing, None, &Members::default()) {
+            db.books.push(fresh);
+        }
+    } else {
+        let mut e = build_entry(p, no_hash, false);
+        roots::assign(&db.roots, &mut e);
+        e.volume = Volumes::new().volume_of(p);
+        merge_entry(db, &mut e);
+        info!("watch: indexed {}", e.full_path);
+    }
+    1
+}
+
+/// Mark entries at `p` (or below it, for a directory) as missing, unless
+/// their whole volume went away (unmount).
+fn mark_missing(db: &mut BooksDb, p: &Path) -> usize {
+    let mut n = 0;
+    let mut vols = Volumes::new();
+    for b in db.books.iter_mut().filter(|b| !b.stale && !b.missing) {
+        if Path::new(&b.full_path).starts_with(p) && !vols.is_offline(b) {
+            b.missing = true;
+            warn!("watch: removed, marked missing: {}", b.full_path);
+            n += 1;
+        }
+    }
+    n
+}
+
+/// Re-point entries at `from` (or below it, for a directory) to `to`.
+fn move_entries(
+    db: &mut BooksDb,
+    from: &Path,
+    to: &Path,
+    admitted: &dyn Fn(&Path) -> bool,
+) -> usize {
+    let mut n = 0;
+    let roots = &db.roots;
+    for b in db.books.iter_mut().filter(|b| !b.stale) {
+        let old = PathBuf::from(&b.full_path);
+        let Ok(rest) = old.strip_prefix(from) else {
+            continue;
+        };
+        let new = if rest.as_os_str().is_empty() {
+            to.to_path_buf()
+        } else {
+            to.join(rest)
+        };
+        if !(is_book_path(&new) || is_exploded_epub(&new)) || !admitted(&new) {
+            b.missing = true;
+            warn!("watch: renamed away, marked missing: {}", b.full_path);
+        } else {
+            roots::relocate(roots, b, &new);
+            b.missing = false;
+            info!("watch: moved {} → {}", old.display(), b.full_path);
+        }
+        n += 1;
+    }
+    n
+}
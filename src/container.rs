@@ -0,0 +1,144 @@
+//! One view over the two shapes an EPUB comes in: a zip file, or an
+//! exploded (unpacked) working directory holding `mimetype`,
+//! `META-INF/container.xml` and the content (`OEBPS/` …).
+//!
+//! Entry names are `/`-separated and relative to the book root in both
+//! cases, so OPF hrefs resolve the same way. The hashes of a directory are
+//! taken over its files in sorted name order (see `hash::xxh3_container`).
+
+use anyhow::{Context, Result, bail};
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Seek};
+use std::path::{Component, Path, PathBuf};
+use zip::ZipArchive;
+
+/// Read access to the entries of a book container.
+pub trait Container {
+    /// Every file entry (no directories), in container order. For a
+    /// directory: regular files only, without dot-files and symlinks.
+    fn names(&self) -> Vec<String>;
+
+    fn contains(&self, name: &str) -> bool;
+
+    /// Uncompressed size and a reader for one entry.
+    fn open(&mut self, name: &str) -> Result<(u64, Box<dyn Read + '_>)>;
+
+    fn read_bytes(&mut self, name: &str) -> Result<Vec<u8>> {
+        let (size, mut r) = self.open(name)?;
+        let mut buf = Vec::with_capacity(size as usize);
+        r.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_string(&mut self, name: &str) -> Result<String> {
+        let (size, mut r) = self.open(name)?;
+        let mut s = String::with_capacity(size as usize);
+        r.read_to_string(&mut s)
+            .with_context(|| format!("read {name}"))?;
+        Ok(s)
+    }
+}
+
+impl<R: Read + Seek> Container for ZipArchive<R> {
+    fn names(&self) -> Vec<String> {
+        self.file_names()
+            .filter(|n| !n.ends_with('/'))
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.index_for_name(name).is_some()
+    }
+
+    fn open(&mut self, name: &str) -> Result<(u64, Box<dyn Read + '_>)> {
+        let f = self
+            .by_name(name)
+            .with_context(|| format!("{name} not found"))?;
+        Ok((f.size(), Box::new(f)))
+    }
+}
+
+/// An exploded EPUB directory.
+#[derive(Debug, Clone)]
+pub struct DirContainer {
+    root: PathBuf,
+}
+
+impl DirContainer {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+        }
+    }
+
+    /// On-disk path of an entry; names that would leave the root are refused.
+    fn path_of(&self, name: &str) -> Option<PathBuf> {
+        let rel = Path::new(name);
+        rel.components()
+            .all(|c| matches!(c, Component::Normal(_)))
+            .then(|| self.root.join(rel))
+    }
+}
+
+impl Container for DirContainer {
+    fn names(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        let mut stack = vec![(self.root.clone(), String::new())];
+        while let Some((dir, prefix)) = stack.pop() {
+            let Ok(rd) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for ent in rd.flatten() {
+                let file_name = ent.file_name().to_string_lossy().into_owned();
+                if file_name.starts_with('.') {
+                    continue;
+                }
+                let name = format!("{prefix}{file_name}");
+                // Symlinks are neither: a link out of the book is not part of it
+                match fs::symlink_metadata(ent.path()) {
+                    Ok(md) if md.is_dir() => stack.push((ent.path(), format!("{name}/"))),
+                    Ok(md) if md.is_file() => out.push(name),
+                    _ => {}
+                }
+            }
+        }
+        out
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.path_of(name).is_some_and(|p| p.is_file())
+    }
+
+    fn open(&mut self, name: &str) -> Result<(u64, Box<dyn Read + '_>)> {
+        let Some(path) = self.path_of(name) else {
+            bail!("invalid entry name {name:?}");
+        };
+        let f = File::open(&path).with_context(|| format!("{name} not found"))?;
+        let size = f.metadata()?.len();
+        Ok((size, Box::new(BufReader::new(f))))
+    }
+}
+
+/// True for a directory laid out as an unpacked EPUB (`mimetype` and
+/// `META-INF/container.xml` at its top).
+pub fn is_exploded_epub(p: &Path) -> bool {
+    p.join("mimetype").is_file() && p.join("META-INF").join("container.xml").is_file()
+}
+
+/// Size on disk of a book: the file's length, or the sum of the file sizes
+/// of an exploded directory.
+pub fn book_size(p: &Path) -> u64 {
+    match fs::metadata(p) {
+        Ok(md) if md.is_dir() => {
+            let dir = DirContainer::new(p);
+            dir.names()
+                .iter()
+                .filter_map(|n| fs::metadata(dir.root.join(n)).ok())
+                .map(|md| md.len())
+                .sum()
+        }
+        Ok(md) => md.len(),
+        Err(_) => 0,
+    }
+}
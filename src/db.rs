@@ -1,4 +1,5 @@
 use crate::model::BooksDb;
+use crate::roots;
 use anyhow::{Context, Result};
 use chrono::Utc;
 use std::fs;
@@ -14,6 +15,7 @@ pub fn load_db<P: AsRef<Path>>(p: P) -> Result<BooksDb> {
     let mut db: BooksDb = serde_json::from_str(&data)
         .with_context(|| format!("parsing JSON DB {}", path.to_string_lossy()))?;
     db.last_updated = Some(Utc::now());
+    roots::resolve_paths(&mut db);
     Ok(db)
 }
 
@@ -0,0 +1,216 @@
+//! DjVu (`.djvu`, `.djv`) metadata extractor:
+//! - `AT&TFORM` header check; IFF chunks walked with seeks, so large scans
+//!   are not read whole
+//! - page count: 1 for single-page `DJVU`, the `FORM:DJVU` components of a
+//!   bundled `DJVM`
+//! - `(metadata …)` from plain `ANTa` annotation chunks: title, author,
+//!   year, publisher, other keys as-is
+//!
+//! `ANTz` annotations and the `NAVM` outline are BZZ-compressed; there is no
+//! BZZ decoder here yet (tracked as planned work in the README), so their
+//! contents are not extracted: `chapters` stays empty, and files that keep
+//! their metadata in `ANTz` (most of them) yield only a page count.
+
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom};
+use tracing::debug;
+
+use crate::model::EpubMeta;
+
+/// Annotation chunks larger than this are skipped.
+const MAX_ANNOTATION: u32 = 1 << 20;
+
+#[derive(Default)]
+struct Scan {
+    pages: usize,
+    bundled: bool,
+    annotations: Vec<String>,
+    compressed: Vec<&'static str>,
+}
+
+pub fn read_djvu_metadata<R: Read + Seek>(mut r: R) -> Result<EpubMeta> {
+    let mut magic = [0u8; 16];
+    r.read_exact(&mut magic).context("read DjVu header")?;
+    if &magic[..8] != b"AT&TFORM" {
+        bail!("not a DjVu file (no AT&TFORM header)");
+    }
+    let len = u32::from_be_bytes([magic[8], magic[9], magic[10], magic[11]]);
+    let end = 12 + u64::from(len);
+
+    let mut s = Scan::default();
+    match &magic[12..16] {
+        b"DJVU" => {
+            s.pages = 1;
+            walk(&mut r, 16, end, &mut s, false)?;
+        }
+        b"DJVM" => walk(&mut r, 16, end, &mut s, true)?,
+        other => bail!("unknown DjVu form {:?}", String::from_utf8_lossy(other)),
+    }
+    if !s.compressed.is_empty() {
+        debug!(
+            "DjVu: not decoding BZZ-compressed {}",
+            s.compressed.join(", ")
+        );
+    }
+
+    let mut meta = EpubMeta::default();
+    let mut other = BTreeMap::new();
+    // Single-page files and bundles only; indirect documents keep pages elsewhere
+    if s.pages > 0 {
+        other.insert("pages".into(), s.pages.to_string());
+    }
+    for (key, value) in s.annotations.iter().flat_map(|a| metadata_pairs(a)) {
+        match key.as_str() {
+            "title" => meta.title = meta.title.or(Some(value)),
+            "author" => meta.author = meta.author.or(Some(value)),
+            "year" => meta.publish_date = meta.publish_date.or(Some(value)),
+            "publisher" => meta.publisher = meta.publisher.or(Some(value)),
+            _ => {
+                other.entry(key).or_insert(value);
+            }
+        }
+    }
+    meta.other_metadata = other;
+    Ok(meta)
+}
+
+/// Visit the chunks in `[at, end)`; `top` is true for the components of a DJVM.
+fn walk<R: Read + Seek>(r: &mut R, mut at: u64, end: u64, s: &mut Scan, top: bool) -> Result<()> {
+    while at + 8 <= end {
+        r.seek(SeekFrom::Start(at))?;
+        let mut head = [0u8; 8];
+        r.read_exact(&mut head)?;
+        let id = [head[0], head[1], head[2], head[3]];
+        let len = u32::from_be_bytes([head[4], head[5], head[6], head[7]]);
+        let body = at + 8;
+        match &id {
+            b"FORM" if top => {
+                let mut kind = [0u8; 4];
+                r.read_exact(&mut kind)?;
+                if &kind == b"DJVU" {
+                    s.pages += 1;
+                }
+                // Shared annotations usually live in a DJVI include
+                if matches!(&kind, b"DJVU" | b"DJVI") {
+                    walk(r, body + 4, body + u64::from(len), s, false)?;
+                }
+            }
+            b"DIRM" if top => {
+                let mut flags = [0u8; 1];
+                r.read_exact(&mut flags)?;
+                s.bundled = flags[0] & 0x80 != 0;
+                if !s.bundled {
+                    // Indirect: pages are separate files, listed in compressed data
+                    s.pages = 0;
+                    return Ok(());
+                }
+            }
+            b"ANTa" if len <= MAX_ANNOTATION => {
+                let mut buf = vec![0u8; len as usize];
+                r.read_exact(&mut buf)?;
+                s.annotations
+                    .push(String::from_utf8_lossy(&buf).into_owned());
+            }
+            b"ANTz" => s.compressed.push("annotations"),
+            b"NAVM" => s.compressed.push("outline"),
+            _ => {}
+        }
+        // IFF chunks are padded to even length
+        at = body + u64::from(len) + u64::from(len & 1);
+    }
+    Ok(())
+}
+
+/// `(key "value")` pairs of every `(metadata …)` form in an annotation chunk.
+fn metadata_pairs(ann: &str) -> Vec<(String, String)> {
+    let tokens = tokenize(ann);
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i + 1 < tokens.len() {
+        if tokens[i] == Tok::Open && tokens[i + 1] == Tok::Atom("metadata".into()) {
+            i += 2;
+            // Inside (metadata …): sequence of (key "value") until the close
+            while let [Tok::Open, Tok::Atom(k), Tok::Str(v), Tok::Close, ..] = &tokens[i..] {
+                let v = v.trim();
+                if !v.is_empty() {
+                    out.push((k.to_ascii_lowercase(), v.to_string()));
+                }
+                i += 4;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    out
+}
+
+#[derive(Debug, PartialEq)]
+enum Tok {
+    Open,
+    Close,
+    Atom(String),
+    Str(String),
+}
+
+/// Lisp-like annotation syntax: parens, bare atoms, and C-style strings
+/// (`\"`, `\\`, `\n`, `\t`, octal `\ooo` bytes of UTF-8).
+fn tokenize(s: &str) -> Vec<Tok> {
+    let b = s.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < b.len() {
+        match b[i] {
+            b'(' => {
+                out.push(Tok::Open);
+                i += 1;
+            }
+            b')' => {
+                out.push(Tok::Close);
+                i += 1;
+            }
+            b'"' => {
+                let mut bytes = Vec::new();
+                i += 1;
+                while i < b.len() && b[i] != b'"' {
+                    if b[i] == b'\\' && i + 1 < b.len() {
+                        i += 1;
+                        let oct = b[i..]
+                            .iter()
+                            .take(3)
+                            .take_while(|c| (b'0'..=b'7').contains(c));
+                        let n = oct.clone().count();
+                        if n > 0 {
+                            let v = oct.fold(0u32, |acc, c| acc * 8 + u32::from(c - b'0'));
+                            bytes.push(v as u8);
+                            i += n;
+                            continue;
+                        }
+                        bytes.push(match b[i] {
+                            b'n' => b'\n',
+                            b't' => b'\t',
+                            c => c,
+                        });
+                    } else {
+                        bytes.push(b[i]);
+                    }
+                    i += 1;
+                }
+                i += 1;
+                out.push(Tok::Str(String::from_utf8_lossy(&bytes).into_owned()));
+            }
+            c if c.is_ascii_whitespace() => i += 1,
+            _ => {
+                let start = i;
+                while i < b.len()
+                    && !b[i].is_ascii_whitespace()
+                    && !matches!(b[i], b'(' | b')' | b'"')
+                {
+                    i += 1;
+                }
+                out.push(Tok::Atom(s[start..i].to_string()));
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,229 @@
+//! FictionBook 2 (`.fb2`, `.fb2.zip`) metadata extractor:
+//! - `.fb2.zip`: the first `.fb2` entry of the archive
+//! - text decoded per the XML declaration (windows-1251 is common)
+//! - `<description><title-info>`: genres, authors, book-title, annotation,
+//!   date, lang, sequence; `<publish-info>`: publisher, ISBN
+//! - `<body>` section titles → chapters (footnote bodies are skipped)
+//! - cover: `<coverpage><image l:href="#id"/>` → base64 `<binary id="id">`
+
+use anyhow::{Context, Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use encoding_rs::Encoding;
+use roxmltree::{Document, Node};
+use std::collections::BTreeMap;
+use std::io::{Read, Seek};
+use std::path::Path;
+use zip::ZipArchive;
+
+use crate::model::EpubMeta;
+
+/// True for `*.fb2.zip` (matched case-insensitively).
+pub fn is_fb2_zip(p: &Path) -> bool {
+    p.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.to_ascii_lowercase().ends_with(".fb2.zip"))
+}
+
+pub fn read_fb2_metadata<R: Read + Seek>(r: R, name: &Path) -> Result<EpubMeta> {
+    let xml = read_fb2(r, name)?;
+    let doc = Document::parse(&xml).context("parse FB2")?;
+    let mut meta = EpubMeta::default();
+    let mut other = BTreeMap::new();
+
+    if let Some(ti) = find(doc.root_element(), &["description", "title-info"]) {
+        meta.title = child_text(ti, "book-title");
+        let authors: Vec<String> = ti
+            .children()
+            .filter(|n| n.has_tag_name("author"))
+            .filter_map(author_name)
+            .collect();
+        meta.author = authors.first().cloned();
+        if authors.len() > 1 {
+            other.insert("creators".into(), authors.join("; "));
+        }
+        let genres: Vec<String> = ti
+            .children()
+            .filter(|n| n.has_tag_name("genre"))
+            .filter_map(text)
+            .collect();
+        if !genres.is_empty() {
+            other.insert("genres".into(), genres.join("; "));
+        }
+        meta.description = ti
+            .children()
+            .find(|n| n.has_tag_name("annotation"))
+            .and_then(paragraphs);
+        // <date value="1965-08-01">1965</date>: the attribute is machine-readable
+        meta.publish_date = ti
+            .children()
+            .find(|n| n.has_tag_name("date"))
+            .and_then(|d| d.attribute("value").map(str::to_string).or_else(|| text(d)));
+        if let Some(lang) = child_text(ti, "lang") {
+            other.insert("language".into(), lang);
+        }
+        if let Some(seq) = ti.children().find(|n| n.has_tag_name("sequence"))
+            && let Some(name) = seq
+                .attribute("name")
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+        {
+            other.insert("series".into(), name.to_string());
+            if let Some(num) = seq
+                .attribute("number")
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+            {
+                other.insert("series_index".into(), num.to_string());
+            }
+        }
+    }
+    if let Some(pi) = find(doc.root_element(), &["description", "publish-info"]) {
+        meta.publisher = child_text(pi, "publisher");
+        if let Some(isbn) = child_text(pi, "isbn") {
+            other.insert("identifier".into(), format!("urn:isbn:{isbn}"));
+        }
+        if meta.publish_date.is_none() {
+            meta.publish_date = child_text(pi, "year");
+        }
+    }
+
+    meta.chapters = doc
+        .root_element()
+        .children()
+        .filter(|n| n.has_tag_name("body") && n.attribute("name").is_none())
+        .flat_map(|b| b.descendants())
+        .filter(|n| {
+            n.has_tag_name("title") && n.parent().is_some_and(|p| p.has_tag_name("section"))
+        })
+        .filter_map(paragraphs)
+        .map(|t| t.replace('\n', " "))
+        .collect();
+    meta.other_metadata = other;
+    Ok(meta)
+}
+
+/// Cover image bytes and media type, decoded from the referenced `<binary>`.
+pub fn read_fb2_cover<R: Read + Seek>(r: R, name: &Path) -> Result<Option<(Vec<u8>, String)>> {
+    let xml = read_fb2(r, name)?;
+    let doc = Document::parse(&xml).context("parse FB2")?;
+    let Some(id) = find(
+        doc.root_element(),
+        &["description", "title-info", "coverpage"],
+    )
+    .and_then(|c| c.children().find(|n| n.has_tag_name("image")))
+    .and_then(|img| img.attributes().find(|a| a.name() == "href"))
+    .map(|a| a.value().trim_start_matches('#').to_string()) else {
+        return Ok(None);
+    };
+    let Some(bin) = doc
+        .root_element()
+        .children()
+        .find(|n| n.has_tag_name("binary") && n.attribute("id") == Some(id.as_str()))
+    else {
+        return Ok(None);
+    };
+    let b64: String = bin
+        .text()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let bytes = STANDARD
+        .decode(b64)
+        .with_context(|| format!("decode cover binary {id:?}"))?;
+    let media_type = bin.attribute("content-type").unwrap_or("image/jpeg");
+    Ok(Some((bytes, media_type.to_string())))
+}
+
+/// The FB2 document as text, unzipped and decoded.
+fn read_fb2<R: Read + Seek>(mut r: R, name: &Path) -> Result<String> {
+    let bytes = if is_fb2_zip(name) {
+        let mut zip = ZipArchive::new(r).context("open zip archive")?;
+        let name = zip
+            .file_names()
+            .find(|n| n.to_ascii_lowercase().ends_with(".fb2"))
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("no .fb2 entry in {}", name.display()))?;
+        let mut buf = Vec::new();
+        zip.by_name(&name)?.read_to_end(&mut buf)?;
+        buf
+    } else {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)
+            .with_context(|| format!("read {}", name.display()))?;
+        buf
+    };
+    Ok(decode(&bytes))
+}
+
+/// Decode by BOM, else by the `encoding="…"` of the XML declaration, else UTF-8.
+fn decode(bytes: &[u8]) -> String {
+    let declared = bytes
+        .get(..bytes.len().min(200))
+        .and_then(|head| {
+            let head = String::from_utf8_lossy(head);
+            let decl = head.split("?>").next()?;
+            let rest = decl.split("encoding=").nth(1)?;
+            let quote = rest.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+            rest[1..].split(quote).next().map(str::to_string)
+        })
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    let (text, _, _) = declared.decode(bytes);
+    // roxmltree only accepts UTF-8 input, so drop the now-wrong declaration
+    match text.strip_prefix("<?xml") {
+        Some(rest) => rest
+            .split_once("?>")
+            .map_or(text.to_string(), |(_, body)| body.to_string()),
+        None => text.into_owned(),
+    }
+}
+
+/// "First Middle Last", else the nickname.
+fn author_name(a: Node) -> Option<String> {
+    let parts: Vec<String> = ["first-name", "middle-name", "last-name"]
+        .iter()
+        .filter_map(|tag| child_text(a, tag))
+        .collect();
+    if parts.is_empty() {
+        child_text(a, "nickname")
+    } else {
+        Some(parts.join(" "))
+    }
+}
+
+/// Follow a path of child element names.
+fn find<'a, 'i>(from: Node<'a, 'i>, path: &[&str]) -> Option<Node<'a, 'i>> {
+    path.iter()
+        .try_fold(from, |n, tag| n.children().find(|c| c.has_tag_name(*tag)))
+}
+
+fn child_text(n: Node, tag: &str) -> Option<String> {
+    n.children().find(|c| c.has_tag_name(tag)).and_then(text)
+}
+
+/// All text below `n`, whitespace collapsed; None if empty.
+fn text(n: Node) -> Option<String> {
+    let s: String = n
+        .descendants()
+        .filter(|d| d.is_text())
+        .filter_map(|d| d.text())
+        .collect();
+    let s = s.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!s.is_empty()).then_some(s)
+}
+
+/// `<p>` children (or the whole node if it has none), one per line.
+fn paragraphs(n: Node) -> Option<String> {
+    let ps: Vec<String> = n
+        .children()
+        .filter(|c| c.has_tag_name("p"))
+        .filter_map(text)
+        .collect();
+    if ps.is_empty() {
+        text(n)
+    } else {
+        Some(ps.join("\n"))
+    }
+}
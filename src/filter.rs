@@ -0,0 +1,101 @@
+//! Entry filters shared by commands that select a subset of the DB
+//! (`export`, and the planned `query`).
+
+use clap::Args;
+
+use crate::model::{BookEntry, FileFormat, ValidationStatus};
+
+/// Selection flags. Text matches are case-insensitive substrings; all given
+/// conditions must hold. Stale (superseded) entries are skipped by default.
+#[derive(Debug, Clone, Default, Args)]
+pub struct EntryFilter {
+    /// Only entries whose title contains TEXT
+    #[arg(long, value_name = "TEXT")]
+    pub title: Option<String>,
+
+    /// Only entries whose author contains TEXT
+    #[arg(long, value_name = "TEXT")]
+    pub author: Option<String>,
+
+    /// Only entries whose publisher contains TEXT
+    #[arg(long, value_name = "TEXT")]
+    pub publisher: Option<String>,
+
+    /// Only entries whose full path contains TEXT
+    #[arg(long, value_name = "TEXT")]
+    pub path: Option<String>,
+
+    /// Only entries of this file format
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub file_format: Option<FileFormat>,
+
+    /// Only entries under this named root
+    #[arg(long, value_name = "NAME")]
+    pub root: Option<String>,
+
+    /// Only entries whose other_metadata KEY contains VALUE (repeatable)
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    pub meta: Vec<(String, String)>,
+
+    /// Only entries with this `validate` result
+    #[arg(long, value_enum, value_name = "STATUS")]
+    pub validation: Option<ValidationStatus>,
+
+    /// Only entries not validated yet
+    #[arg(long, conflicts_with = "validation")]
+    pub unvalidated: bool,
+
+    /// Only DRM-protected entries
+    #[arg(long, conflicts_with = "no_drm")]
+    pub drm: bool,
+
+    /// Only entries without DRM
+    #[arg(long)]
+    pub no_drm: bool,
+
+    /// Only entries marked missing
+    #[arg(long)]
+    pub missing: bool,
+
+    /// Also include stale (superseded) entries
+    #[arg(long)]
+    pub include_stale: bool,
+}
+
+impl EntryFilter {
+    pub fn matches(&self, e: &BookEntry) -> bool {
+        (self.include_stale || !e.stale)
+            && (!self.missing || e.missing)
+            && contains(e.title.as_deref(), self.title.as_deref())
+            && contains(e.author.as_deref(), self.author.as_deref())
+            && contains(e.publisher.as_deref(), self.publisher.as_deref())
+            && contains(Some(&e.full_path), self.path.as_deref())
+            && self.file_format.is_none_or(|f| e.format == f)
+            && self.validation.is_none_or(|v| e.validation == Some(v))
+            && (!self.unvalidated || e.validation.is_none())
+            && (!self.drm || e.drm.is_some())
+            && (!self.no_drm || e.drm.is_none())
+            && self
+                .root
+                .as_deref()
+                .is_none_or(|r| e.root.as_deref() == Some(r))
+            && self
+                .meta
+                .iter()
+                .all(|(k, v)| contains(e.other_metadata.get(k).map(String::as_str), Some(v)))
+    }
+}
+
+/// `needle` absent → always true; otherwise case-insensitive substring test.
+fn contains(haystack: Option<&str>, needle: Option<&str>) -> bool {
+    match needle {
+        None => true,
+        Some(n) => haystack.is_some_and(|h| h.to_lowercase().contains(&n.to_lowercase())),
+    }
+}
+
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(k, v)| (k.trim().to_string(), v.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got {s:?}"))
+}
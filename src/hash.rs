@@ -1,12 +1,37 @@
 use anyhow::{Context, Result};
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{BufReader, Read, Seek, SeekFrom};
 use std::path::Path;
 use xxhash_rust::xxh3::Xxh3;
+use zip::read::ZipArchive;
 
+use crate::container::{Container, DirContainer};
+use crate::model::FileFormat;
+
+/// Archive members that only carry reader/library state (bookmarks, store
+/// receipts) and never change the book itself. Skipped by the content hash.
+pub const CONTENT_HASH_EXCLUDES: &[&str] = &[
+    "calibre_bookmarks.txt",
+    "iTunesMetadata.plist",
+    "iTunesArtwork",
+];
+
+/// Bytes sampled from each of the start, middle and end of a file by
+/// `quick_hash`. Fixed so stored quick hashes stay comparable across runs.
+pub const QUICK_HASH_CHUNK: u64 = 64 * 1024;
+
+/// XXH3-128 of a file, or of an exploded EPUB directory (its files in
+/// canonical order, see `xxh3_container`).
 pub fn xxh3_file(path: &Path) -> Result<u128> {
+    if path.is_dir() {
+        return xxh3_container(&mut DirContainer::new(path), &[]);
+    }
     let f = File::open(path).with_context(|| format!("open for hash: {}", path.display()))?;
-    let mut rdr = BufReader::new(f);
+    xxh3_reader(BufReader::new(f))
+}
+
+/// XXH3-128 of everything `rdr` yields, streamed.
+pub fn xxh3_reader<R: Read>(mut rdr: R) -> Result<u128> {
     let mut hasher = Xxh3::new();
     let mut buf = vec![0u8; 1 << 16];
     loop {
@@ -18,3 +43,137 @@ pub fn xxh3_file(path: &Path) -> Result<u128> {
     }
     Ok(hasher.digest128())
 }
+
+/// Quick partial hash: XXH3-128 over the file size plus the first, middle and
+/// last `QUICK_HASH_CHUNK` bytes. Reads at most 3 chunks regardless of file
+/// size, so it is cheap on slow (network) storage. Equal quick hashes are only
+/// a candidate match; confirm with `xxh3_file`.
+///
+/// For an exploded EPUB directory: the sorted (name, size, mtime) listing,
+/// no reads.
+pub fn quick_hash(path: &Path) -> Result<u128> {
+    if path.is_dir() {
+        return quick_hash_dir(path);
+    }
+    let f = File::open(path).with_context(|| format!("open for hash: {}", path.display()))?;
+    let size = f.metadata()?.len();
+    quick_hash_reader(f, size)
+}
+
+fn quick_hash_dir(path: &Path) -> Result<u128> {
+    let mut names = DirContainer::new(path).names();
+    names.sort();
+    let mut hasher = Xxh3::new();
+    for name in &names {
+        let md = std::fs::metadata(path.join(name)).with_context(|| format!("stat {name}"))?;
+        // An in-place edit keeping the size still moves the mtime
+        let mtime = md
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos());
+        hasher.update(&(name.len() as u64).to_le_bytes());
+        hasher.update(name.as_bytes());
+        hasher.update(&md.len().to_le_bytes());
+        hasher.update(&mtime.to_le_bytes());
+    }
+    Ok(hasher.digest128())
+}
+
+/// `quick_hash` of a seekable source of `size` bytes.
+pub fn quick_hash_reader<R: Read + Seek>(mut f: R, size: u64) -> Result<u128> {
+    let mut hasher = Xxh3::new();
+    hasher.update(&size.to_le_bytes());
+
+    let mut buf = vec![0u8; QUICK_HASH_CHUNK as usize];
+    let windows = if size <= 3 * QUICK_HASH_CHUNK {
+        // Small file: the three windows would overlap, just take it whole.
+        vec![(0, size)]
+    } else {
+        vec![
+            (0, QUICK_HASH_CHUNK),
+            (size / 2 - QUICK_HASH_CHUNK / 2, QUICK_HASH_CHUNK),
+            (size - QUICK_HASH_CHUNK, QUICK_HASH_CHUNK),
+        ]
+    };
+    for (off, len) in windows {
+        f.seek(SeekFrom::Start(off))?;
+        let mut rdr = (&mut f).take(len);
+        loop {
+            let n = rdr.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+    }
+    Ok(hasher.digest128())
+}
+
+/// Canonical content hash of a ZIP-based book (EPUB):
+/// XXH3-128 over the (entry name, uncompressed bytes) pairs, sorted by name.
+/// Re-zipping with another compression level, entry order or timestamps
+/// yields the same value. Members whose name (or final path component)
+/// appears in `exclude` are skipped.
+pub fn xxh3_zip_content(path: &Path, exclude: &[&str]) -> Result<u128> {
+    let f = File::open(path).with_context(|| format!("open for hash: {}", path.display()))?;
+    xxh3_zip_content_reader(BufReader::new(f), exclude)
+}
+
+pub fn xxh3_zip_content_reader<R: Read + Seek>(r: R, exclude: &[&str]) -> Result<u128> {
+    let mut zip = ZipArchive::new(r).with_context(|| "open zip archive")?;
+    xxh3_container(&mut zip, exclude)
+}
+
+/// The canonical hash behind `xxh3_zip_content`, for any container. An
+/// exploded directory hashes the same as the EPUB zipped from it.
+pub fn xxh3_container<C: Container + ?Sized>(c: &mut C, exclude: &[&str]) -> Result<u128> {
+    let mut names: Vec<String> = c
+        .names()
+        .into_iter()
+        .filter(|n| {
+            let base = n.rsplit('/').next().unwrap_or(n);
+            !exclude.iter().any(|x| *x == *n || *x == base)
+        })
+        .collect();
+    names.sort();
+
+    let mut hasher = Xxh3::new();
+    let mut buf = vec![0u8; 1 << 16];
+    for name in &names {
+        let (size, mut entry) = c.open(name)?;
+        // Length-prefix name and body so (name, bytes) boundaries are unambiguous.
+        hasher.update(&(name.len() as u64).to_le_bytes());
+        hasher.update(name.as_bytes());
+        hasher.update(&size.to_le_bytes());
+        loop {
+            let n = entry.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+    }
+    Ok(hasher.digest128())
+}
+
+/// Content hash for formats that have one (ZIP-based: EPUB and CBZ; a CBR
+/// or CB7 comic does not open as a zip and gets none); `None` otherwise or
+/// when the archive cannot be read.
+pub fn content_hash(path: &Path, format: FileFormat) -> Option<u128> {
+    if !format.has_content_hash() {
+        return None;
+    }
+    if format == FileFormat::Epub && path.is_dir() {
+        return xxh3_container(&mut DirContainer::new(path), CONTENT_HASH_EXCLUDES).ok();
+    }
+    xxh3_zip_content(path, CONTENT_HASH_EXCLUDES).ok()
+}
+
+/// `content_hash` of a book held in a seekable source (e.g. an archive member).
+pub fn content_hash_reader<R: Read + Seek>(r: R, format: FileFormat) -> Option<u128> {
+    if !format.has_content_hash() {
+        return None;
+    }
+    xxh3_zip_content_reader(r, CONTENT_HASH_EXCLUDES).ok()
+}
@@ -13,5 +13,6 @@ pub fn init(level: &str) {
         .with_target(false) // cleaner lines
         .with_ansi(true) // force colors when TTY
         .with_level(true)
+        .with_writer(std::io::stderr) // stdout stays clean for command output
         .try_init();
 }
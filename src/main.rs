@@ -1,12 +1,27 @@
+mod archive;
 mod args;
+mod biblio;
+mod comic;
 mod commands;
+mod container;
 mod db;
+mod djvu;
+mod fb2;
+mod filter;
 mod hash;
 mod log;
 mod metadata;
+mod mobi;
 mod model;
+mod opf_edit;
+mod remote;
+mod report;
+mod roots;
+mod s3;
 mod scan;
 mod util;
+mod validate;
+mod volume;
 
 use anyhow::Result;
 use clap::Parser;
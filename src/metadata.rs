@@ -1,27 +1,35 @@
 //! Minimal EPUB metadata extractor:
-//! - open ZIP
+//! - open the container (ZIP, or an exploded directory)
 //! - read META-INF/container.xml → locate OPF
 //! - parse OPF for dc:title, dc:creator, dc:description, dc:publisher, dc:date
+//!   (plus language, identifier, series, and all creators/contributors with roles)
+//!
+//! - detect DRM from META-INF (rights.xml, license.lcpl, sinf.xml, encryption.xml)
+//!
 //! (Best effort; chapters left empty for now.)
 
-use crate::model::EpubMeta;
+use crate::comic;
+use crate::container::{Container, DirContainer};
+use crate::djvu;
+use crate::fb2;
+use crate::mobi;
+use crate::model::{DrmScheme, EpubMeta, FileFormat};
 use anyhow::{Context, Result, anyhow};
+use percent_encoding::percent_decode_str;
 use roxmltree::Document;
-use std::io::Read;
+use std::io::{BufReader, Read, Seek};
 use std::path::Path;
 use std::{collections::BTreeMap, fs::File};
 use zip::read::ZipArchive;
 
-pub fn extract_epub_metadata(path: &Path) -> Result<EpubMeta> {
-    let file = File::open(path).with_context(|| format!("open epub: {}", path.display()))?;
-    let mut zip = ZipArchive::new(file).with_context(|| "open zip archive")?;
-
+/// Locate the OPF via META-INF/container.xml; returns (OPF entry name, OPF XML).
+pub fn read_opf<C: Container + ?Sized>(c: &mut C) -> Result<(String, String)> {
     // 1) container.xml
-    let mut container_xml = String::new();
-    zip.by_name("META-INF/container.xml")
-        .map_err(|_| anyhow!("container.xml not found"))?
-        .read_to_string(&mut container_xml)?;
-    let doc = Document::parse(&container_xml)?;
+    if !c.contains("META-INF/container.xml") {
+        return Err(anyhow!("container.xml not found"));
+    }
+    let container_xml = c.read_string("META-INF/container.xml")?;
+    let doc = Document::parse(&container_xml).context("parse container.xml")?;
     let rootfile = doc
         .descendants()
         .find(|n| n.has_tag_name("rootfile"))
@@ -30,12 +38,222 @@ pub fn extract_epub_metadata(path: &Path) -> Result<EpubMeta> {
         .to_string();
 
     // 2) OPF
-    let mut opf_xml = String::new();
-    zip.by_name(&rootfile)
-        .map_err(|_| anyhow!("OPF not found at {rootfile}"))?
-        .read_to_string(&mut opf_xml)?;
+    if !c.contains(&rootfile) {
+        return Err(anyhow!("OPF not found at {rootfile}"));
+    }
+    let opf_xml = c.read_string(&rootfile)?;
+    Ok((rootfile, opf_xml))
+}
+
+/// Resolve an href (relative to `base`, e.g. the OPF; percent-encoded) to a zip entry name.
+pub fn resolve_href(base: &str, href: &str) -> String {
+    let href = percent_decode_str(href.split('#').next().unwrap_or(href)).decode_utf8_lossy();
+    let mut parts: Vec<&str> = base.split('/').collect();
+    parts.pop(); // base file name
+    for seg in href.split('/') {
+        match seg {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            s => parts.push(s),
+        }
+    }
+    parts.join("/")
+}
+
+/// Cover image of an EPUB as (bytes, media type): the manifest item with
+/// `properties="cover-image"` (EPUB3) or the one named by `<meta name="cover">` (EPUB2).
+pub fn read_epub_cover<R: Read + Seek>(r: R) -> Result<Option<(Vec<u8>, String)>> {
+    let mut zip = ZipArchive::new(r).with_context(|| "open zip archive")?;
+    epub_cover(&mut zip)
+}
+
+/// `read_epub_cover` for any container (zip or exploded directory).
+pub fn epub_cover<C: Container + ?Sized>(c: &mut C) -> Result<Option<(Vec<u8>, String)>> {
+    let (opf_path, opf_xml) = read_opf(c)?;
     let opf = Document::parse(&opf_xml)?;
 
+    let items = || opf.descendants().filter(|n| n.has_tag_name("item"));
+    let cover_id = opf
+        .descendants()
+        .find(|n| n.has_tag_name("meta") && n.attribute("name") == Some("cover"))
+        .and_then(|n| n.attribute("content"));
+    let item = items()
+        .find(|n| {
+            n.attribute("properties")
+                .is_some_and(|p| p.split_whitespace().any(|p| p == "cover-image"))
+        })
+        .or_else(|| items().find(|n| cover_id.is_some() && n.attribute("id") == cover_id));
+    let Some(item) = item else {
+        return Ok(None);
+    };
+    let (Some(href), media_type) = (item.attribute("href"), item.attribute("media-type")) else {
+        return Ok(None);
+    };
+    if !media_type.unwrap_or("image/").starts_with("image/") {
+        return Ok(None);
+    }
+
+    let name = resolve_href(&opf_path, href);
+    if !c.contains(&name) {
+        return Err(anyhow!("cover not found at {name}"));
+    }
+    let bytes = c.read_bytes(&name)?;
+    Ok(Some((
+        bytes,
+        media_type.unwrap_or("image/jpeg").to_string(),
+    )))
+}
+
+/// Metadata for any indexed format; `path` may be an exploded EPUB directory.
+pub fn extract_metadata(path: &Path, format: FileFormat) -> Result<EpubMeta> {
+    if format == FileFormat::Epub && path.is_dir() {
+        return epub_metadata(&mut DirContainer::new(path));
+    }
+    let file = File::open(path).with_context(|| format!("open: {}", path.display()))?;
+    read_metadata(BufReader::new(file), path, format)
+}
+
+/// Metadata from any seekable source (a file, or an archive member in
+/// memory); `name` is only used for its file name / extension.
+pub fn read_metadata<R: Read + Seek>(r: R, name: &Path, format: FileFormat) -> Result<EpubMeta> {
+    match format {
+        FileFormat::Epub => read_epub_metadata(r),
+        FileFormat::Pdf => extract_pdf_metadata(name),
+        FileFormat::Mobi => mobi::read_mobi_metadata(r),
+        FileFormat::Fb2 => fb2::read_fb2_metadata(r, name),
+        FileFormat::Comic => comic::read_comic_metadata(r, name),
+        FileFormat::Djvu => djvu::read_djvu_metadata(r),
+    }
+}
+
+/// Cover image as (bytes, media type), for formats that carry one.
+pub fn extract_cover(path: &Path, format: FileFormat) -> Result<Option<(Vec<u8>, String)>> {
+    if format == FileFormat::Epub && path.is_dir() {
+        return epub_cover(&mut DirContainer::new(path));
+    }
+    let file = File::open(path).with_context(|| format!("open: {}", path.display()))?;
+    read_cover(BufReader::new(file), path, format)
+}
+
+pub fn read_cover<R: Read + Seek>(
+    r: R,
+    name: &Path,
+    format: FileFormat,
+) -> Result<Option<(Vec<u8>, String)>> {
+    match format {
+        FileFormat::Epub => read_epub_cover(r),
+        FileFormat::Mobi => mobi::read_mobi_cover(r),
+        FileFormat::Fb2 => fb2::read_fb2_cover(r, name),
+        FileFormat::Comic => comic::read_comic_cover(r, name),
+        FileFormat::Pdf | FileFormat::Djvu => Ok(None),
+    }
+}
+
+pub fn read_epub_metadata<R: Read + Seek>(r: R) -> Result<EpubMeta> {
+    let mut zip = ZipArchive::new(r).with_context(|| "open zip archive")?;
+    epub_metadata(&mut zip)
+}
+
+/// `read_epub_metadata` for any container (zip or exploded directory).
+pub fn epub_metadata<C: Container + ?Sized>(c: &mut C) -> Result<EpubMeta> {
+    let (_, opf_xml) = read_opf(c)?;
+    let mut meta = parse_opf(&opf_xml)?;
+    let enc = read_encryption(c);
+    meta.drm = enc.drm;
+    if let Some(kind) = enc.font_obfuscation {
+        meta.other_metadata
+            .insert("font_obfuscation".into(), kind.into());
+    }
+    Ok(meta)
+}
+
+/// Font obfuscation algorithms (IDPF, Adobe): these only scramble embedded
+/// fonts against extraction; the book stays readable, so they are not DRM.
+const IDPF_OBFUSCATION: &str = "http://www.idpf.org/2008/embedding";
+const ADOBE_OBFUSCATION: &str = "http://ns.adobe.com/pdf/enc#RC";
+
+/// What META-INF says about encryption.
+#[derive(Debug, Default)]
+pub struct Encryption {
+    pub drm: Option<DrmScheme>,
+    /// "idpf" or "adobe" when fonts are obfuscated
+    pub font_obfuscation: Option<&'static str>,
+    /// Container entry names encrypted under a DRM scheme (not font obfuscation)
+    pub encrypted: Vec<String>,
+}
+
+/// Classify a book's encryption. The license file names the scheme (ADEPT
+/// `rights.xml`, LCP `license.lcpl`, FairPlay `sinf.xml`); otherwise
+/// `encryption.xml` entries that are not font obfuscation mean unknown DRM.
+pub fn read_encryption<C: Container + ?Sized>(c: &mut C) -> Encryption {
+    let mut enc = Encryption {
+        drm: if c.contains("META-INF/license.lcpl") {
+            Some(DrmScheme::ReadiumLcp)
+        } else if c.contains("META-INF/rights.xml") {
+            Some(DrmScheme::AdobeAdept)
+        } else if c.contains("META-INF/sinf.xml") {
+            Some(DrmScheme::AppleFairplay)
+        } else {
+            None
+        },
+        ..Encryption::default()
+    };
+
+    let xml = if c.contains("META-INF/encryption.xml") {
+        c.read_string("META-INF/encryption.xml").ok()
+    } else {
+        None
+    };
+    let Some(doc) = xml.as_deref().and_then(|x| Document::parse(x).ok()) else {
+        return enc;
+    };
+    let mut lcp_key = false;
+    for data in doc
+        .descendants()
+        .filter(|n| n.has_tag_name("EncryptedData"))
+    {
+        let algorithm = data
+            .descendants()
+            .find(|n| n.has_tag_name("EncryptionMethod"))
+            .and_then(|n| n.attribute("Algorithm"))
+            .unwrap_or_default();
+        let uri = data
+            .descendants()
+            .find(|n| n.has_tag_name("CipherReference"))
+            .and_then(|n| n.attribute("URI"));
+        match algorithm {
+            IDPF_OBFUSCATION => enc.font_obfuscation = Some("idpf"),
+            ADOBE_OBFUSCATION => enc.font_obfuscation = Some("adobe"),
+            _ => {
+                // LCP content keys point back into the license document
+                lcp_key |= data.descendants().any(|n| {
+                    n.has_tag_name("RetrievalMethod")
+                        && n.attribute("URI")
+                            .is_some_and(|u| u.contains("license.lcpl"))
+                });
+                // URIs are relative to the container root
+                if let Some(uri) = uri {
+                    enc.encrypted.push(resolve_href("", uri));
+                }
+            }
+        }
+    }
+    if enc.drm.is_none() && !enc.encrypted.is_empty() {
+        enc.drm = Some(if lcp_key {
+            DrmScheme::ReadiumLcp
+        } else {
+            DrmScheme::Unknown
+        });
+    }
+    enc
+}
+
+/// Parse OPF package metadata (also used for Calibre's standalone `metadata.opf`).
+pub fn parse_opf(opf_xml: &str) -> Result<EpubMeta> {
+    let opf = Document::parse(opf_xml)?;
+
     let ns_dc = "http://purl.org/dc/elements/1.1/";
 
     let text_of = |tag: &str| {
@@ -63,6 +281,80 @@ pub fn extract_epub_metadata(path: &Path) -> Result<EpubMeta> {
         other.insert("identifier".into(), ident);
     }
 
+    // Every dc:creator / dc:contributor as "Name" or "Name (role)", where role is
+    // the MARC relator from opf:role (EPUB2) or a refining <meta property="role"> (EPUB3).
+    let people_of = |tag: &str| -> Vec<String> {
+        opf.descendants()
+            .filter(|n| n.tag_name().name() == tag && n.tag_name().namespace() == Some(ns_dc))
+            .filter_map(|n| {
+                let name = n.text()?.trim();
+                if name.is_empty() {
+                    return None;
+                }
+                let role = n
+                    .attributes()
+                    .find(|a| a.name() == "role")
+                    .map(|a| a.value().to_string())
+                    .or_else(|| {
+                        let refines = format!("#{}", n.attribute("id")?);
+                        opf.descendants()
+                            .find(|m| {
+                                m.has_tag_name("meta")
+                                    && m.attribute("refines") == Some(refines.as_str())
+                                    && m.attribute("property") == Some("role")
+                            })
+                            .and_then(|m| m.text())
+                            .map(|t| t.trim().to_string())
+                    });
+                Some(match role {
+                    Some(r) => format!("{name} ({r})"),
+                    None => name.to_string(),
+                })
+            })
+            .collect()
+    };
+    // Series: Calibre's <meta name="calibre:series"> or EPUB3 belongs-to-collection
+    let meta_content = |name: &str| {
+        opf.descendants()
+            .find(|n| n.has_tag_name("meta") && n.attribute("name") == Some(name))
+            .and_then(|n| n.attribute("content"))
+            .map(|s| s.trim().to_string())
+    };
+    let collection = opf.descendants().find(|n| {
+        n.has_tag_name("meta") && n.attribute("property") == Some("belongs-to-collection")
+    });
+    let series = meta_content("calibre:series").or_else(|| {
+        collection
+            .and_then(|n| n.text())
+            .map(|t| t.trim().to_string())
+    });
+    let series_index = meta_content("calibre:series_index").or_else(|| {
+        let refines = format!("#{}", collection?.attribute("id")?);
+        opf.descendants()
+            .find(|m| {
+                m.has_tag_name("meta")
+                    && m.attribute("refines") == Some(refines.as_str())
+                    && m.attribute("property") == Some("group-position")
+            })
+            .and_then(|m| m.text())
+            .map(|t| t.trim().to_string())
+    });
+    if let Some(series) = series.filter(|s| !s.is_empty()) {
+        other.insert("series".into(), series);
+        if let Some(idx) = series_index {
+            other.insert("series_index".into(), idx);
+        }
+    }
+
+    let creators = people_of("creator");
+    if creators.len() > 1 {
+        other.insert("creators".into(), creators.join("; "));
+    }
+    let contributors = people_of("contributor");
+    if !contributors.is_empty() {
+        other.insert("contributors".into(), contributors.join("; "));
+    }
+
     Ok(EpubMeta {
         title,
         author,
@@ -71,6 +363,7 @@ pub fn extract_epub_metadata(path: &Path) -> Result<EpubMeta> {
         publish_date,
         publisher,
         other_metadata: other,
+        drm: None,
     })
 }
 
@@ -91,5 +384,6 @@ pub fn extract_pdf_metadata(path: &std::path::Path) -> anyhow::Result<crate::mod
         publish_date: None,
         publisher: None,
         other_metadata: BTreeMap::new(),
+        drm: None,
     })
 }
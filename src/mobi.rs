@@ -0,0 +1,295 @@
+//! Minimal MOBI / AZW / AZW3 (KF8) / PRC metadata extractor:
+//! - PalmDB header → record offsets; only record 0 and the cover record are
+//!   read (seeking to them), not the text
+//! - record 0: PalmDOC header (encryption) + MOBI header (full name, locale,
+//!   text encoding, first image record, EXTH flag)
+//! - EXTH records: author, publisher, description, ISBN, date, ASIN,
+//!   updated title, language, cover offset, KF8 boundary
+//!
+//! Text records are not decompressed, so chapters stay empty (as for EPUB).
+
+use anyhow::{Context, Result, anyhow};
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::model::{DrmScheme, EpubMeta};
+
+const PALMDB_HEADER: usize = 78;
+
+/// Parsed container header: database name, type and record offsets.
+struct PalmDb {
+    name: [u8; 32],
+    kind: [u8; 8],
+    records: Vec<u64>,
+    len: u64,
+}
+
+impl PalmDb {
+    fn read<R: Read + Seek>(r: &mut R) -> Result<Self> {
+        let len = r.seek(SeekFrom::End(0))?;
+        r.seek(SeekFrom::Start(0))?;
+        let mut head = [0u8; PALMDB_HEADER];
+        r.read_exact(&mut head)
+            .context("too short for a PalmDB header")?;
+        let mut name = [0u8; 32];
+        name.copy_from_slice(&head[..32]);
+        let mut kind = [0u8; 8];
+        kind.copy_from_slice(&head[60..68]);
+        let n = u16_at(&head, 76)? as usize;
+        let mut table = vec![0u8; n * 8];
+        r.read_exact(&mut table)
+            .context("truncated PalmDB record list")?;
+        let records = (0..n)
+            .map(|i| u32_at(&table, i * 8).map(u64::from))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(PalmDb {
+            name,
+            kind,
+            records,
+            len,
+        })
+    }
+
+    /// Bytes of record `i` (up to the next record or end of file), read
+    /// from `r` at its offset.
+    fn record<R: Read + Seek>(&self, r: &mut R, i: usize) -> Result<Option<Vec<u8>>> {
+        let Some(&start) = self.records.get(i).filter(|&&s| s <= self.len) else {
+            return Ok(None);
+        };
+        let end = self.records.get(i + 1).copied().unwrap_or(self.len);
+        let end = end.clamp(start, self.len);
+        let mut buf = vec![0u8; (end - start) as usize];
+        r.seek(SeekFrom::Start(start))?;
+        r.read_exact(&mut buf)?;
+        Ok(Some(buf))
+    }
+}
+
+/// Fields from record 0 that matter to us.
+struct Header<'a> {
+    rec0: &'a [u8],
+    encryption: u16,
+    utf8: bool,
+    full_name: Option<String>,
+    locale: u32,
+    version: u32,
+    first_image: Option<usize>,
+    exth: Vec<(u32, &'a [u8])>,
+}
+
+fn parse_header<'a>(db: &PalmDb, rec0: &'a [u8]) -> Result<Header<'a>> {
+    if &db.kind != b"BOOKMOBI" || rec0.get(16..20) != Some(b"MOBI") {
+        // Plain PalmDOC (TEXtREAd): no MOBI header, only the database name.
+        return Ok(Header {
+            rec0,
+            encryption: 0,
+            utf8: false,
+            full_name: None,
+            locale: 0,
+            version: 0,
+            first_image: None,
+            exth: Vec::new(),
+        });
+    }
+    let encryption = u16_at(rec0, 12)?;
+    let header_len = u32_at(rec0, 20)? as usize;
+    let utf8 = u32_at(rec0, 28)? == 65001;
+    let version = u32_at(rec0, 36).unwrap_or(0);
+    let name_off = u32_at(rec0, 84)? as usize;
+    let name_len = u32_at(rec0, 88)? as usize;
+    let full_name = rec0
+        .get(name_off..name_off + name_len)
+        .map(|b| decode(b, utf8));
+    let locale = u32_at(rec0, 92).unwrap_or(0);
+    let first_image = u32_at(rec0, 108)
+        .ok()
+        .filter(|&i| i != u32::MAX)
+        .map(|i| i as usize);
+    let has_exth = u32_at(rec0, 128).is_ok_and(|f| f & 0x40 != 0);
+    let exth = if has_exth {
+        parse_exth(rec0, 16 + header_len)
+    } else {
+        Vec::new()
+    };
+    Ok(Header {
+        rec0,
+        encryption,
+        utf8,
+        full_name,
+        locale,
+        version,
+        first_image,
+        exth,
+    })
+}
+
+/// EXTH block at `at`: "EXTH", header length, count, then (type, length, data).
+fn parse_exth(rec0: &[u8], at: usize) -> Vec<(u32, &[u8])> {
+    let mut out = Vec::new();
+    if rec0.get(at..at + 4) != Some(b"EXTH") {
+        return out;
+    }
+    let count = u32_at(rec0, at + 8).unwrap_or(0);
+    let mut pos = at + 12;
+    for _ in 0..count {
+        let (Ok(kind), Ok(len)) = (u32_at(rec0, pos), u32_at(rec0, pos + 4)) else {
+            break;
+        };
+        let len = len as usize;
+        let Some(data) = rec0.get(pos + 8..pos + len.max(8)) else {
+            break;
+        };
+        out.push((kind, data));
+        pos += len.max(8);
+    }
+    out
+}
+
+pub fn read_mobi_metadata<R: Read + Seek>(mut r: R) -> Result<EpubMeta> {
+    let db = PalmDb::read(&mut r)?;
+    let rec0 = db
+        .record(&mut r, 0)?
+        .ok_or_else(|| anyhow!("no record 0"))?;
+    let h = parse_header(&db, &rec0)?;
+    let text = |kind: u32| -> Vec<String> {
+        h.exth
+            .iter()
+            .filter(|(k, _)| *k == kind)
+            .map(|(_, d)| decode(d, h.utf8).trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect()
+    };
+    let first = |kind: u32| text(kind).into_iter().next();
+
+    let palm_name = {
+        let raw = &db.name;
+        let end = raw.iter().position(|&b| b == 0).unwrap_or(32);
+        decode(&raw[..end], false).replace('_', " ")
+    };
+    let title = first(503)
+        .or(h.full_name.clone())
+        .filter(|t| !t.is_empty())
+        .or(Some(palm_name).filter(|t| !t.is_empty()));
+
+    let authors = text(100);
+    let mut other = BTreeMap::new();
+    if authors.len() > 1 {
+        other.insert("creators".into(), authors.join("; "));
+    }
+    if let Some(lang) = first(524).or_else(|| locale_language(h.locale).map(str::to_string)) {
+        other.insert("language".into(), lang);
+    }
+    if let Some(isbn) = first(104) {
+        other.insert("identifier".into(), format!("urn:isbn:{isbn}"));
+    }
+    if let Some(asin) = first(113).or_else(|| first(504)) {
+        other.insert("asin".into(), asin);
+    }
+    if let Some(cover) = cover_record(&h) {
+        other.insert("cover_record".into(), cover.to_string());
+    }
+    let kf8_boundary = h.exth.iter().any(|(k, _)| *k == 121);
+    let kindle_format = match (h.version >= 8, kf8_boundary) {
+        (true, _) => Some("kf8"),
+        (false, true) => Some("mobi7+kf8"),
+        (false, false) if h.rec0.get(16..20) == Some(b"MOBI") => Some("mobi7"),
+        _ => None,
+    };
+    if let Some(k) = kindle_format {
+        other.insert("kindle_format".into(), k.into());
+    }
+
+    Ok(EpubMeta {
+        title,
+        author: authors.first().cloned(),
+        description: first(103),
+        chapters: Vec::new(),
+        publish_date: first(106),
+        publisher: first(101),
+        other_metadata: other,
+        // PalmDOC encryption: 1 = old Mobipocket, 2 = Mobipocket/Kindle PID DRM
+        drm: (h.encryption != 0).then_some(DrmScheme::Mobipocket),
+    })
+}
+
+/// Cover image bytes and media type (sniffed from the magic bytes).
+pub fn read_mobi_cover<R: Read + Seek>(mut r: R) -> Result<Option<(Vec<u8>, String)>> {
+    let db = PalmDb::read(&mut r)?;
+    let rec0 = db
+        .record(&mut r, 0)?
+        .ok_or_else(|| anyhow!("no record 0"))?;
+    let h = parse_header(&db, &rec0)?;
+    let Some(i) = cover_record(&h) else {
+        return Ok(None);
+    };
+    let Some(bytes) = db.record(&mut r, i)? else {
+        return Ok(None);
+    };
+    let media_type = match bytes.as_slice() {
+        [0xFF, 0xD8, ..] => "image/jpeg",
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [b'G', b'I', b'F', ..] => "image/gif",
+        _ => return Ok(None),
+    };
+    Ok(Some((bytes, media_type.into())))
+}
+
+/// Record index of the cover: first image record + EXTH 201 offset.
+fn cover_record(h: &Header) -> Option<usize> {
+    let offset = h.exth.iter().find(|(k, _)| *k == 201).map(|(_, d)| *d)?;
+    let offset = u32_at(offset, 0).ok().filter(|&o| o != u32::MAX)?;
+    Some(h.first_image? + offset as usize)
+}
+
+/// Windows LCID primary language → ISO 639-1 (the common ones).
+fn locale_language(locale: u32) -> Option<&'static str> {
+    Some(match locale & 0xFF {
+        0x01 => "ar",
+        0x04 => "zh",
+        0x05 => "cs",
+        0x06 => "da",
+        0x07 => "de",
+        0x08 => "el",
+        0x09 => "en",
+        0x0A => "es",
+        0x0B => "fi",
+        0x0C => "fr",
+        0x0D => "he",
+        0x0E => "hu",
+        0x10 => "it",
+        0x11 => "ja",
+        0x12 => "ko",
+        0x13 => "nl",
+        0x14 => "no",
+        0x15 => "pl",
+        0x16 => "pt",
+        0x19 => "ru",
+        0x1D => "sv",
+        0x1F => "tr",
+        _ => return None,
+    })
+}
+
+/// UTF-8 when the header says so; otherwise CP1252.
+fn decode(b: &[u8], utf8: bool) -> String {
+    if utf8 {
+        String::from_utf8_lossy(b).into_owned()
+    } else {
+        encoding_rs::WINDOWS_1252
+            .decode_without_bom_handling(b)
+            .0
+            .into_owned()
+    }
+}
+
+fn u16_at(b: &[u8], at: usize) -> Result<u16> {
+    b.get(at..at + 2)
+        .map(|s| u16::from_be_bytes([s[0], s[1]]))
+        .ok_or_else(|| anyhow!("truncated header at {at}"))
+}
+
+fn u32_at(b: &[u8], at: usize) -> Result<u32> {
+    b.get(at..at + 4)
+        .map(|s| u32::from_be_bytes([s[0], s[1], s[2], s[3]]))
+        .ok_or_else(|| anyhow!("truncated header at {at}"))
+}
@@ -2,6 +2,8 @@ use chrono::{DateTime, Utc};
 use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use xxhash_rust::xxh3::xxh3_64;
 
 #[derive(Debug, Clone, Copy, ValueEnum, Serialize, Deserialize, Default)]
 pub enum Verbosity {
@@ -14,13 +16,131 @@ pub enum Verbosity {
     Debug,
 }
 
+/// What commands print on stdout (`--output`).
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// How `diff` pairs the entries of two DBs.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq, Default)]
+pub enum MatchBy {
+    /// Same `full_path`
+    #[default]
+    Path,
+    /// Same file content wherever it lives (renames, other mountpoints)
+    Hash,
+}
+
+/// Output format of `export`.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Tsv,
+    Jsonl,
+    Bibtex,
+    CslJson,
+    Ris,
+}
+
 /// File format of the indexed item.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, ValueEnum, Serialize, Deserialize, PartialEq, Eq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum FileFormat {
     #[default]
     Epub,
     Pdf,
+    /// Kindle / Mobipocket: .mobi, .azw, .azw3 (KF8), .prc
+    Mobi,
+    /// FictionBook 2: .fb2, .fb2.zip
+    Fb2,
+    /// Comic book archive: .cbz, .cbr, .cb7
+    Comic,
+    /// DjVu scans: .djvu, .djv
+    Djvu,
+}
+
+impl FileFormat {
+    /// Format of a file by extension; None for files we do not index.
+    pub fn from_path(p: &Path) -> Option<FileFormat> {
+        let ext = p.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "epub" => Some(FileFormat::Epub),
+            "pdf" => Some(FileFormat::Pdf),
+            "mobi" | "azw" | "azw3" | "prc" => Some(FileFormat::Mobi),
+            "fb2" => Some(FileFormat::Fb2),
+            "cbz" | "cbr" | "cb7" => Some(FileFormat::Comic),
+            "djvu" | "djv" => Some(FileFormat::Djvu),
+            "zip" if crate::fb2::is_fb2_zip(p) => Some(FileFormat::Fb2),
+            _ => None,
+        }
+    }
+
+    /// Whether `hash::content_hash` covers this format (zip-based books,
+    /// hashed over their members so a re-zip keeps the hash).
+    pub fn has_content_hash(self) -> bool {
+        match self {
+            FileFormat::Epub | FileFormat::Comic => true,
+            FileFormat::Pdf | FileFormat::Mobi | FileFormat::Fb2 | FileFormat::Djvu => false,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FileFormat::Epub => "epub",
+            FileFormat::Pdf => "pdf",
+            FileFormat::Mobi => "mobi",
+            FileFormat::Fb2 => "fb2",
+            FileFormat::Comic => "comic",
+            FileFormat::Djvu => "djvu",
+        }
+    }
+}
+
+/// Outcome of `validate`: no issues, only warnings, or at least one error.
+#[derive(Debug, Clone, Copy, ValueEnum, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ValidationStatus {
+    Valid,
+    Warnings,
+    Invalid,
+}
+
+impl ValidationStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ValidationStatus::Valid => "valid",
+            ValidationStatus::Warnings => "warnings",
+            ValidationStatus::Invalid => "invalid",
+        }
+    }
+}
+
+/// DRM scheme protecting a book's content (font obfuscation is not DRM).
+#[derive(Debug, Clone, Copy, ValueEnum, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DrmScheme {
+    AdobeAdept,
+    ReadiumLcp,
+    AppleFairplay,
+    /// Mobipocket / Kindle PID encryption (MOBI, AZW)
+    Mobipocket,
+    /// Encrypted resources under an unrecognized scheme
+    Unknown,
+}
+
+impl DrmScheme {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DrmScheme::AdobeAdept => "adobe-adept",
+            DrmScheme::ReadiumLcp => "readium-lcp",
+            DrmScheme::AppleFairplay => "apple-fairplay",
+            DrmScheme::Mobipocket => "mobipocket",
+            DrmScheme::Unknown => "unknown",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -32,16 +152,40 @@ pub struct EpubMeta {
     pub publish_date: Option<String>,
     pub publisher: Option<String>,
     pub other_metadata: BTreeMap<String, String>,
+    #[serde(default)]
+    pub drm: Option<DrmScheme>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
 pub struct BookEntry {
     pub full_path: String,
+    // Named root this entry lives under, and its '/'-separated path below it.
+    // full_path/uri_path are derived from these when set. Null for old DBs.
+    #[serde(default)]
+    pub root: Option<String>,
+    #[serde(default)]
+    pub rel_path: Option<String>,
+    // Volume the file was last seen on ("marker:<label>" or "uuid:<fs uuid>").
+    // Null when unknown (network FS, old DBs).
+    #[serde(default)]
+    pub volume: Option<String>,
     pub uri_path: String,
-    pub protocol: String, // "file" for now
+    pub protocol: String, // "file", "zip"/"tar" (archive members), "webdav", "sftp", "s3"
     pub filename: String,
     pub xxhash: Option<u128>, // XXH3 128-bit
-    pub date_found: String,   // ISO 8601
+    // XXH3 128-bit over sorted (entry name, uncompressed bytes); survives re-zipping.
+    // Null for non-archive formats and old DBs.
+    #[serde(default)]
+    pub content_hash: Option<u128>,
+    // XXH3 128-bit over size + first/middle/last 64 KiB (see hash::quick_hash).
+    // Candidate matching only; confirm with xxhash. Null for old DBs.
+    #[serde(default)]
+    pub quick_hash: Option<u128>,
+    // Remote change detector: the server's ETag (WebDAV, S3) or "mtime:<secs>"
+    // (SFTP), checked with size_bytes before re-downloading. Null for local files.
+    #[serde(default)]
+    pub etag: Option<String>,
+    pub date_found: String, // ISO 8601
     pub missing: bool,
     pub stale: bool,
 
@@ -53,6 +197,17 @@ pub struct BookEntry {
     #[serde(default)]
     pub format: FileFormat,
 
+    // Result of the last `validate` of this content (null = not validated),
+    // and its issues as "error: …" / "warning: …".
+    #[serde(default)]
+    pub validation: Option<ValidationStatus>,
+    #[serde(default)]
+    pub validation_issues: Vec<String>,
+
+    // DRM scheme, if the content is encrypted (null = readable).
+    #[serde(default)]
+    pub drm: Option<DrmScheme>,
+
     // Metadata
     pub title: Option<String>,
     pub author: Option<String>,
@@ -63,8 +218,25 @@ pub struct BookEntry {
     pub other_metadata: BTreeMap<String, String>,
 }
 
+impl BookEntry {
+    /// Short id for the CLI and catalog pages: XXH3-64 of `full_path` in hex.
+    pub fn id(&self) -> String {
+        format!("{:016x}", xxh3_64(self.full_path.as_bytes()))
+    }
+
+    /// Drop the `validate` result: a re-zipped file keeps its content but
+    /// not its zip layout (`mimetype` order and compression).
+    pub fn clear_validation(&mut self) {
+        self.validation = None;
+        self.validation_issues.clear();
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct BooksDb {
+    // Named library roots: name → absolute directory (mountpoint).
+    #[serde(default)]
+    pub roots: BTreeMap<String, PathBuf>,
     pub books: Vec<BookEntry>,
     pub last_updated: Option<DateTime<Utc>>,
 }
@@ -0,0 +1,318 @@
+//! Write metadata edits back into an EPUB:
+//! - splice the OPF text (only the touched `dc:*` / `meta` elements change)
+//! - repack the zip: `mimetype` first and stored, other entries copied raw,
+//!   the OPF re-deflated; written to a temp file, then renamed over the book
+//! - exploded EPUB directories: only the OPF file is replaced (same way)
+
+use anyhow::{Context, Result, anyhow, bail};
+use roxmltree::{Document, Node};
+use std::fs::{self, File};
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::container::DirContainer;
+use crate::metadata::read_opf;
+use crate::model::BookEntry;
+
+const NS_DC: &str = "http://purl.org/dc/elements/1.1/";
+
+/// Requested changes; `None` / empty leaves a field alone.
+#[derive(Debug, Clone, Default)]
+pub struct MetaEdit {
+    pub title: Option<String>,
+    /// First one is the main author.
+    pub authors: Vec<String>,
+    /// `Some("")` removes the book from its series.
+    pub series: Option<String>,
+    pub series_index: Option<String>,
+}
+
+impl MetaEdit {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.authors.is_empty() && self.series.is_none()
+    }
+
+    /// Apply to a DB record, using the same keys `read_epub_metadata` fills.
+    pub fn apply(&self, e: &mut BookEntry) {
+        if let Some(t) = &self.title {
+            e.title = Some(t.clone());
+        }
+        if let Some(first) = self.authors.first() {
+            e.author = Some(first.clone());
+            if self.authors.len() > 1 {
+                e.other_metadata
+                    .insert("creators".into(), self.authors.join("; "));
+            } else {
+                e.other_metadata.remove("creators");
+            }
+        }
+        if let Some(s) = &self.series {
+            e.other_metadata.remove("series_index");
+            if s.is_empty() {
+                e.other_metadata.remove("series");
+            } else {
+                e.other_metadata.insert("series".into(), s.clone());
+                if let Some(i) = &self.series_index {
+                    e.other_metadata.insert("series_index".into(), i.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Rewrite the OPF of the EPUB at `path` in place.
+pub fn write_epub_opf(path: &Path, edit: &MetaEdit) -> Result<()> {
+    if path.is_dir() {
+        return write_dir_opf(path, edit);
+    }
+    let file = File::open(path).with_context(|| format!("open epub: {}", path.display()))?;
+    let mut zip = ZipArchive::new(file).with_context(|| "open zip archive")?;
+    let (opf_path, opf_xml) = read_opf(&mut zip)?;
+    let new_opf = edit_opf(&opf_xml, edit)?;
+
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("not a file: {}", path.display()))?;
+    let tmp = path.with_file_name(format!(".{}.epubr-tmp", name.to_string_lossy()));
+    let written =
+        repack(&mut zip, &tmp, &opf_path, &new_opf).and_then(|_| copy_permissions(path, &tmp));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    fs::rename(&tmp, path).with_context(|| format!("replace {}", path.display()))?;
+    Ok(())
+}
+
+fn write_dir_opf(root: &Path, edit: &MetaEdit) -> Result<()> {
+    let (opf_path, opf_xml) = read_opf(&mut DirContainer::new(root))?;
+    let new_opf = edit_opf(&opf_xml, edit)?;
+    let opf = root.join(&opf_path);
+    let tmp = opf.with_file_name(format!(
+        ".{}.epubr-tmp",
+        opf.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    let written = fs::write(&tmp, new_opf.as_bytes())
+        .and_then(|_| File::open(&tmp)?.sync_all())
+        .with_context(|| format!("write {}", tmp.display()))
+        .and_then(|_| copy_permissions(&opf, &tmp));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    fs::rename(&tmp, &opf).with_context(|| format!("replace {}", opf.display()))?;
+    Ok(())
+}
+
+/// Give the replacement `tmp` the mode of the file it is about to replace.
+fn copy_permissions(orig: &Path, tmp: &Path) -> Result<()> {
+    let perms = fs::metadata(orig)
+        .with_context(|| format!("stat {}", orig.display()))?
+        .permissions();
+    fs::set_permissions(tmp, perms).with_context(|| format!("chmod {}", tmp.display()))
+}
+
+fn repack(zip: &mut ZipArchive<File>, tmp: &Path, opf_path: &str, opf: &str) -> Result<()> {
+    let out = File::create(tmp).with_context(|| format!("create {}", tmp.display()))?;
+    let mut w = ZipWriter::new(out);
+    // OCF: mimetype must be the first entry, uncompressed, without extra fields
+    w.start_file(
+        "mimetype",
+        SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+    )?;
+    w.write_all(b"application/epub+zip")?;
+
+    for i in 0..zip.len() {
+        let f = zip.by_index_raw(i)?;
+        let entry = f.name().to_string();
+        if entry == "mimetype" {
+            continue;
+        }
+        if entry == opf_path {
+            drop(f);
+            w.start_file(
+                entry,
+                SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
+            )?;
+            w.write_all(opf.as_bytes())?;
+        } else {
+            w.raw_copy_file(f)?;
+        }
+    }
+    w.finish()?.sync_all()?;
+    Ok(())
+}
+
+/// Return the OPF text with `edit` applied. Untouched markup is kept byte for byte.
+pub fn edit_opf(xml: &str, edit: &MetaEdit) -> Result<String> {
+    let doc = Document::parse(xml)?;
+    let metadata = doc
+        .descendants()
+        .find(|n| n.has_tag_name("metadata"))
+        .ok_or_else(|| anyhow!("OPF has no <metadata>"))?;
+    let Some(close) = xml[metadata.range()]
+        .rfind("</")
+        .map(|p| p + metadata.range().start)
+    else {
+        bail!("OPF <metadata> is empty");
+    };
+    let epub3 = doc
+        .root_element()
+        .attribute("version")
+        .is_some_and(|v| v.starts_with('3'));
+
+    let indent = metadata
+        .children()
+        .rfind(|n| n.is_element())
+        .map(|n| line_indent(xml, n.range().start))
+        .unwrap_or("    ");
+    let dc = |local: &str, text: &str| match metadata.lookup_prefix(NS_DC) {
+        Some(p) => format!("<{p}:{local}>{}</{p}:{local}>", esc(text)),
+        None => format!(
+            "<dc:{local} xmlns:dc=\"{NS_DC}\">{}</dc:{local}>",
+            esc(text)
+        ),
+    };
+    let is_dc = |n: &Node, local: &str| {
+        n.tag_name().name() == local && n.tag_name().namespace() == Some(NS_DC)
+    };
+
+    let mut splices: Vec<(Range<usize>, String)> = Vec::new();
+    // New elements go where the old ones were, else at the end of <metadata>.
+    let replace = |old: Vec<Node>, new: Vec<String>| {
+        let joined = new.join(&format!("\n{indent}"));
+        let mut out = Vec::new();
+        match old.split_first() {
+            Some((first, rest)) => {
+                out.push((first.range(), joined));
+                for n in rest {
+                    out.push((with_leading_ws(xml, n.range()), String::new()));
+                }
+            }
+            None if !new.is_empty() => {
+                let at = xml[..close].trim_end().len();
+                out.push((at..at, format!("\n{indent}{joined}")));
+            }
+            None => {}
+        }
+        out
+    };
+    let refining = |ids: &[&str]| -> Vec<Node> {
+        metadata
+            .children()
+            .filter(|m| {
+                m.has_tag_name("meta")
+                    && m.attribute("refines")
+                        .is_some_and(|r| ids.iter().any(|id| r == format!("#{id}")))
+            })
+            .collect()
+    };
+
+    if let Some(title) = &edit.title {
+        let old = metadata.children().find(|n| is_dc(n, "title"));
+        match old.and_then(|n| inner_range(&n)) {
+            Some(r) => splices.push((r, esc(title))),
+            None => splices.extend(replace(old.into_iter().collect(), vec![dc("title", title)])),
+        }
+    }
+
+    if !edit.authors.is_empty() {
+        let creators: Vec<Node> = metadata
+            .children()
+            .filter(|n| is_dc(n, "creator"))
+            .collect();
+        let ids: Vec<&str> = creators.iter().filter_map(|n| n.attribute("id")).collect();
+        let mut old = creators.clone();
+        old.extend(refining(&ids));
+        old.sort_by_key(|n| n.range().start);
+        let new = edit.authors.iter().map(|a| dc("creator", a)).collect();
+        splices.extend(replace(old, new));
+    }
+
+    if let Some(series) = &edit.series {
+        let mut old: Vec<Node> = metadata
+            .children()
+            .filter(|n| {
+                n.has_tag_name("meta")
+                    && (matches!(
+                        n.attribute("name"),
+                        Some("calibre:series" | "calibre:series_index")
+                    ) || n.attribute("property") == Some("belongs-to-collection"))
+            })
+            .collect();
+        let ids: Vec<&str> = old.iter().filter_map(|n| n.attribute("id")).collect();
+        old.extend(refining(&ids));
+        old.sort_by_key(|n| n.range().start);
+
+        let mut new = Vec::new();
+        if !series.is_empty() {
+            new.push(format!(
+                "<meta name=\"calibre:series\" content=\"{}\"/>",
+                esc(series)
+            ));
+            if let Some(i) = &edit.series_index {
+                new.push(format!(
+                    "<meta name=\"calibre:series_index\" content=\"{}\"/>",
+                    esc(i)
+                ));
+            }
+            if epub3 {
+                new.push(format!(
+                    "<meta property=\"belongs-to-collection\" id=\"epubr-series\">{}</meta>",
+                    esc(series)
+                ));
+                new.push(
+                    "<meta refines=\"#epubr-series\" property=\"collection-type\">series</meta>"
+                        .into(),
+                );
+                if let Some(i) = &edit.series_index {
+                    new.push(format!(
+                        "<meta refines=\"#epubr-series\" property=\"group-position\">{}</meta>",
+                        esc(i)
+                    ));
+                }
+            }
+        }
+        splices.extend(replace(old, new));
+    }
+
+    // Apply back to front so earlier ranges stay valid.
+    splices.sort_by_key(|(r, _)| std::cmp::Reverse((r.start, r.end)));
+    let mut out = xml.to_string();
+    for (r, text) in splices {
+        out.replace_range(r, &text);
+    }
+    Document::parse(&out).context("edited OPF is not well-formed")?;
+    Ok(out)
+}
+
+/// Byte range of an element's content, if it has any.
+fn inner_range(n: &Node) -> Option<Range<usize>> {
+    Some(n.first_child()?.range().start..n.last_child()?.range().end)
+}
+
+/// Whitespace between the start of the line and `pos`.
+fn line_indent(xml: &str, pos: usize) -> &str {
+    let line = xml[..pos].rfind('\n').map_or(0, |p| p + 1);
+    let ws = &xml[line..pos];
+    if ws.chars().all(char::is_whitespace) {
+        ws
+    } else {
+        ""
+    }
+}
+
+/// Extend a removal back over the indentation/newline before the element.
+fn with_leading_ws(xml: &str, r: Range<usize>) -> Range<usize> {
+    xml[..r.start].trim_end().len()..r.end
+}
+
+fn esc(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
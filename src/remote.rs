@@ -0,0 +1,796 @@
+//! Remote library sources: `webdav://` (HTTP), `webdavs://` (HTTPS),
+//! `sftp://` and `s3://` (see `s3.rs`) roots for `load`, with
+//! `check`/`rehash`/`validate`/`site` reaching the same files later.
+//!
+//! - listing: recursive `PROPFIND` (Depth 1), SFTP `readdir`, or S3
+//!   ListObjectsV2
+//! - metadata without downloading: `RangeReader` serves `Read + Seek` from
+//!   ranged reads, so a zip's central directory and OPF are all that travel
+//! - hashing: one streamed download, spooled to a temp file so the content
+//!   hash, quick hash and metadata need no second transfer
+//! - change detection: size + ETag (WebDAV, S3) or size + mtime (SFTP)
+//!   before anything is re-downloaded
+//!
+//! Entries keep the remote URI as `full_path` (decoded, as typed) and
+//! `uri_path` (percent-encoded). Passwords never go in the URI: WebDAV uses
+//! `EPUBR_WEBDAV_USER` / `EPUBR_WEBDAV_PASSWORD` (Basic auth), SFTP uses
+//! `EPUBR_SFTP_PASSWORD`, `EPUBR_SFTP_KEY`, the ssh agent, or
+//! `~/.ssh/id_ed25519` / `~/.ssh/id_rsa`, and only hosts already in
+//! `~/.ssh/known_hosts`.
+
+use anyhow::{Context, Result, anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use percent_encoding::{AsciiSet, CONTROLS, percent_decode_str, utf8_percent_encode};
+use roxmltree::Document;
+use ssh2::{CheckResult, ErrorCode, KnownHostFileKind, Session};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+use tracing::warn;
+use ureq::Agent;
+use ureq::http::{Request, Response};
+
+use crate::hash;
+use crate::metadata;
+use crate::model::{BookEntry, FileFormat};
+use crate::report;
+use crate::s3::S3;
+use crate::util::now_iso8601;
+
+/// Ranged reads are made in blocks of this size…
+const BLOCK: u64 = 64 * 1024;
+/// …and this many recent blocks are kept per reader.
+const CACHED_BLOCKS: usize = 16;
+
+/// Characters escaped in `uri_path` and request URLs; `/` separates segments.
+const PATH_ESCAPE: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b']')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<propfind xmlns="DAV:"><prop><resourcetype/><getcontentlength/><getetag/></prop></propfind>"#;
+
+/// One remote file, as listed.
+#[derive(Debug, Clone)]
+pub struct RemoteFile {
+    /// Absolute, decoded path on the server.
+    pub path: String,
+    pub size: u64,
+    /// Change detector: the ETag, or "mtime:<secs>" for SFTP.
+    pub etag: Option<String>,
+}
+
+/// A remote file tree.
+pub trait Source: Send + Sync {
+    /// Every file below `root` (or `root` itself, if it is a file).
+    fn list(&self, root: &str) -> Result<Vec<RemoteFile>>;
+
+    /// Current size and ETag of one file; `None` if it is gone.
+    fn stat(&self, path: &str) -> Result<Option<RemoteFile>>;
+
+    /// `len` bytes from `start` (fewer at the end of the file).
+    fn read_range(&self, path: &str, start: u64, len: u64) -> Result<Vec<u8>>;
+
+    /// The whole file, streamed.
+    fn open(&self, path: &str) -> Result<Box<dyn Read + '_>>;
+}
+
+/// A parsed `webdav://`, `webdavs://`, `sftp://` or `s3://` URI (for S3,
+/// `host` is the bucket and `path` the key prefix).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteUri {
+    pub scheme: String,
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+    /// Absolute, decoded path ("/" for the server root).
+    pub path: String,
+}
+
+impl RemoteUri {
+    pub fn parse(s: &str) -> Result<RemoteUri> {
+        let (scheme, rest) = s
+            .split_once("://")
+            .ok_or_else(|| anyhow!("not a remote URI: {s}"))?;
+        let scheme = scheme.to_ascii_lowercase();
+        if !matches!(scheme.as_str(), "webdav" | "webdavs" | "sftp" | "s3") {
+            bail!("unsupported scheme {scheme:?} (use webdav://, webdavs://, sftp:// or s3://)");
+        }
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let (user, hostport) = match authority.rsplit_once('@') {
+            Some((userinfo, _)) if userinfo.contains(':') => bail!(
+                "put passwords in the environment (EPUBR_WEBDAV_PASSWORD / EPUBR_SFTP_PASSWORD), not in the URI"
+            ),
+            Some((user, hp)) => (
+                Some(percent_decode_str(user).decode_utf8_lossy().into_owned()),
+                hp,
+            ),
+            None => (None, authority),
+        };
+        // [v6::addr]:port or host:port
+        let (host, port) = match hostport.rsplit_once(':') {
+            Some((h, p)) if !p.contains(']') => (
+                h,
+                Some(
+                    p.parse::<u16>()
+                        .with_context(|| format!("bad port in {s}"))?,
+                ),
+            ),
+            _ => (hostport, None),
+        };
+        if host.is_empty() {
+            bail!("no host in {s}");
+        }
+        let path = percent_decode_str(path).decode_utf8_lossy();
+        let path = match path.trim_end_matches('/') {
+            "" => "/".to_string(),
+            p => p.to_string(),
+        };
+        Ok(RemoteUri {
+            scheme,
+            user,
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+
+    /// `BookEntry::protocol` for files from this source.
+    pub fn protocol(&self) -> &'static str {
+        match self.scheme.as_str() {
+            "sftp" => "sftp",
+            "s3" => "s3",
+            _ => "webdav",
+        }
+    }
+
+    /// `scheme://[user@]host[:port]`: identifies one server connection.
+    fn base(&self) -> String {
+        let user = self
+            .user
+            .as_deref()
+            .map(|u| format!("{}@", utf8_percent_encode(u, PATH_ESCAPE)))
+            .unwrap_or_default();
+        let port = self.port.map(|p| format!(":{p}")).unwrap_or_default();
+        format!("{}://{user}{}{port}", self.scheme, self.host)
+    }
+
+    /// `full_path` of a file on this server (decoded).
+    pub fn full_path(&self, path: &str) -> String {
+        format!("{}{path}", self.base())
+    }
+
+    /// `uri_path` of a file on this server (percent-encoded).
+    pub fn uri(&self, path: &str) -> String {
+        format!("{}{}", self.base(), utf8_percent_encode(path, PATH_ESCAPE))
+    }
+}
+
+/// True for `webdav://`, `webdavs://`, `sftp://` and `s3://` URIs.
+pub fn is_remote_uri(s: &str) -> bool {
+    let lower = s.to_ascii_lowercase();
+    ["webdav://", "webdavs://", "sftp://", "s3://"]
+        .iter()
+        .any(|p| lower.starts_with(p))
+}
+
+/// A link a browser can follow: WebDAV URIs as `http(s)://`, others as is.
+pub fn web_url(uri: &str) -> String {
+    if let Some(rest) = uri.strip_prefix("webdavs://") {
+        format!("https://{rest}")
+    } else if let Some(rest) = uri.strip_prefix("webdav://") {
+        format!("http://{rest}")
+    } else {
+        uri.to_string()
+    }
+}
+
+/// True for entries indexed from a remote source.
+pub fn is_remote(e: &BookEntry) -> bool {
+    matches!(e.protocol.as_str(), "webdav" | "sftp" | "s3")
+}
+
+/// A connection, or why it could not be made.
+type Connected = Result<Arc<dyn Source>, String>;
+
+/// Connections, one per server, shared by every command in this process.
+/// Failures are remembered too, so an unreachable host fails fast.
+static SOURCES: LazyLock<Mutex<HashMap<String, Connected>>> = LazyLock::new(Default::default);
+
+/// The (cached) connection for a URI's server.
+pub fn source(u: &RemoteUri) -> Result<Arc<dyn Source>> {
+    let mut sources = SOURCES.lock().unwrap_or_else(|e| e.into_inner());
+    let entry = sources.entry(u.base()).or_insert_with(|| {
+        let src: Result<Arc<dyn Source>> = match u.protocol() {
+            "sftp" => Sftp::connect(u).map(|s| Arc::new(s) as Arc<dyn Source>),
+            "s3" => S3::connect(u).map(|s| Arc::new(s) as Arc<dyn Source>),
+            _ => WebDav::connect(u).map(|s| Arc::new(s) as Arc<dyn Source>),
+        };
+        src.map_err(|e| format!("{}: {e:#}", u.base()))
+    });
+    entry.clone().map_err(|e| anyhow!(e))
+}
+
+/// Connection and server path of a remote entry.
+pub fn locate(e: &BookEntry) -> Result<(Arc<dyn Source>, String)> {
+    let u = RemoteUri::parse(&e.full_path)?;
+    Ok((source(&u)?, u.path))
+}
+
+/// Fresh entry for one listed file. Mirrors `load::build_entry`: with
+/// `no_hash` or `quick_only`, metadata (and the quick hash) come from ranged
+/// reads; otherwise the file is downloaded once and everything is taken from
+/// that copy.
+pub fn build_entry(
+    u: &RemoteUri,
+    src: &Arc<dyn Source>,
+    f: &RemoteFile,
+    no_hash: bool,
+    quick_only: bool,
+) -> BookEntry {
+    let name = Path::new(&f.path);
+    let format = FileFormat::from_path(name).unwrap_or_default();
+    let (meta, xxhash, quick_hash, content_hash) = if no_hash || quick_only {
+        let meta = metadata::read_metadata(RangeReader::new(src, &f.path, f.size), name, format);
+        let quick = (!no_hash)
+            .then(|| hash::quick_hash_reader(RangeReader::new(src, &f.path, f.size), f.size).ok())
+            .flatten();
+        (meta, None, quick, None)
+    } else {
+        match fetch(src.as_ref(), &f.path, format) {
+            Ok(got) => (
+                metadata::read_metadata(BufReader::new(&got.file), name, format),
+                Some(got.xxhash),
+                got.quick_hash,
+                got.content_hash,
+            ),
+            Err(e) => {
+                warn!("{}: {:#}", u.full_path(&f.path), e);
+                report::file_error(u.full_path(&f.path), &e);
+                (Err(e), None, None, None)
+            }
+        }
+    };
+    let meta = meta.unwrap_or_default();
+
+    BookEntry {
+        full_path: u.full_path(&f.path),
+        root: None,
+        rel_path: None,
+        volume: None,
+        uri_path: u.uri(&f.path),
+        protocol: u.protocol().into(),
+        filename: f.path.rsplit('/').next().unwrap_or_default().to_string(),
+        xxhash,
+        content_hash,
+        quick_hash,
+        etag: f.etag.clone(),
+        date_found: now_iso8601(),
+        missing: false,
+        stale: false,
+        size_bytes: f.size,
+        format,
+        validation: None,
+        validation_issues: Vec::new(),
+        drm: meta.drm,
+        title: meta.title,
+        author: meta.author,
+        description: meta.description,
+        chapters: meta.chapters,
+        publish_date: meta.publish_date,
+        publisher: meta.publisher,
+        other_metadata: meta.other_metadata,
+    }
+}
+
+/// A downloaded copy and the hashes taken from it.
+pub struct Fetched {
+    /// Temp file holding the download; removed on drop.
+    pub file: std::fs::File,
+    pub size: u64,
+    pub xxhash: u128,
+    pub quick_hash: Option<u128>,
+    pub content_hash: Option<u128>,
+}
+
+/// Download `path` once: XXH3 while streaming into a temp file, then the
+/// quick and content hashes from that file.
+pub fn fetch(src: &dyn Source, path: &str, format: FileFormat) -> Result<Fetched> {
+    let (mut file, size, xxhash) =
+        spool(src.open(path)?).with_context(|| format!("download {path}"))?;
+    let quick_hash = hash::quick_hash_reader(&file, size).ok();
+    let content_hash = hash::content_hash_reader(BufReader::new(&file), format);
+    file.rewind()?;
+    Ok(Fetched {
+        file,
+        size,
+        xxhash,
+        quick_hash,
+        content_hash,
+    })
+}
+
+/// Copy `rdr` into an anonymous temp file (deleted when dropped), with its
+/// XXH3 computed on the way. Returns the rewound file, its size and hash.
+pub fn spool<R: Read>(rdr: R) -> Result<(File, u64, u128)> {
+    let mut file = tempfile::tempfile().context("create temp file")?;
+    let xxhash = hash::xxh3_reader(Tee {
+        inner: rdr,
+        out: io::BufWriter::new(&mut file),
+    })?;
+    let size = file.seek(SeekFrom::End(0))?;
+    file.rewind()?;
+    Ok((file, size, xxhash))
+}
+
+/// Reader that copies what it reads into `out`.
+struct Tee<R, W> {
+    inner: R,
+    out: W,
+}
+
+impl<R: Read, W: Write> Read for Tee<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 {
+            self.out.flush()?;
+        } else {
+            self.out.write_all(&buf[..n])?;
+        }
+        Ok(n)
+    }
+}
+
+/// `Read + Seek` over a remote file, fetched in `BLOCK`-sized ranged reads
+/// on demand (so zip readers only pull the central directory and the
+/// entries they open).
+pub struct RangeReader {
+    src: Arc<dyn Source>,
+    path: String,
+    size: u64,
+    pos: u64,
+    blocks: Vec<(u64, Vec<u8>)>,
+}
+
+impl RangeReader {
+    pub fn new(src: &Arc<dyn Source>, path: &str, size: u64) -> RangeReader {
+        RangeReader {
+            src: Arc::clone(src),
+            path: path.to_string(),
+            size,
+            pos: 0,
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Reader over a remote entry's file, sized by a fresh `stat`.
+    pub fn for_entry(e: &BookEntry) -> Result<RangeReader> {
+        let (src, path) = locate(e)?;
+        let f = src
+            .stat(&path)?
+            .ok_or_else(|| anyhow!("gone: {}", e.full_path))?;
+        Ok(RangeReader::new(&src, &path, f.size))
+    }
+}
+
+impl Read for RangeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let start = self.pos / BLOCK * BLOCK;
+        let i = match self.blocks.iter().position(|(s, _)| *s == start) {
+            Some(i) => i,
+            None => {
+                let len = BLOCK.min(self.size - start);
+                let data = self
+                    .src
+                    .read_range(&self.path, start, len)
+                    .map_err(io::Error::other)?;
+                if data.is_empty() {
+                    return Ok(0);
+                }
+                if self.blocks.len() == CACHED_BLOCKS {
+                    self.blocks.remove(0);
+                }
+                self.blocks.push((start, data));
+                self.blocks.len() - 1
+            }
+        };
+        let data = &self.blocks[i].1;
+        let off = (self.pos - start) as usize;
+        if off >= data.len() {
+            return Ok(0);
+        }
+        let n = buf.len().min(data.len() - off);
+        buf[..n].copy_from_slice(&data[off..off + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for RangeReader {
+    fn seek(&mut self, to: SeekFrom) -> io::Result<u64> {
+        let pos = match to {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => self.size.checked_add_signed(d),
+            SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+        };
+        self.pos = pos.ok_or_else(|| io::Error::other("seek before start"))?;
+        Ok(self.pos)
+    }
+}
+
+/// HTTP client for WebDAV and S3: statuses are returned, not raised, and
+/// PROPFIND is allowed.
+pub fn http_agent() -> Agent {
+    Agent::config_builder()
+        .http_status_as_error(false)
+        .allow_non_standard_methods(true)
+        .timeout_connect(Some(Duration::from_secs(30)))
+        .build()
+        .into()
+}
+
+/// Run the request made by `build`. A pooled connection the server already
+/// closed fails once; that is retried on a fresh one.
+pub fn send<'a>(
+    agent: &Agent,
+    build: impl Fn() -> ureq::http::Result<Request<&'a str>>,
+) -> Result<Response<ureq::Body>> {
+    let resp = match agent.run(build()?) {
+        Err(ureq::Error::Io(_)) => agent.run(build()?),
+        other => other,
+    };
+    Ok(resp?)
+}
+
+/// WebDAV over HTTP(S).
+struct WebDav {
+    agent: Agent,
+    /// `http(s)://host[:port]`
+    http_base: String,
+    /// `Authorization` header value
+    auth: Option<String>,
+}
+
+impl WebDav {
+    fn connect(u: &RemoteUri) -> Result<WebDav> {
+        let http = if u.scheme == "webdavs" {
+            "https"
+        } else {
+            "http"
+        };
+        let port = u.port.map(|p| format!(":{p}")).unwrap_or_default();
+        let user = u
+            .user
+            .clone()
+            .or_else(|| env::var("EPUBR_WEBDAV_USER").ok());
+        let auth = user.map(|user| {
+            let pass = env::var("EPUBR_WEBDAV_PASSWORD").unwrap_or_default();
+            format!("Basic {}", STANDARD.encode(format!("{user}:{pass}")))
+        });
+        Ok(WebDav {
+            agent: http_agent(),
+            http_base: format!("{http}://{}{port}", u.host),
+            auth,
+        })
+    }
+
+    fn request(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> Result<Response<ureq::Body>> {
+        let url = format!(
+            "{}{}",
+            self.http_base,
+            utf8_percent_encode(path, PATH_ESCAPE)
+        );
+        let build = || {
+            let mut req = Request::builder().method(method).uri(&url);
+            if let Some(auth) = &self.auth {
+                req = req.header("Authorization", auth);
+            }
+            for (k, v) in headers {
+                req = req.header(*k, *v);
+            }
+            req.body(body)
+        };
+        let resp = send(&self.agent, build).with_context(|| format!("{method} {url}"))?;
+        match resp.status().as_u16() {
+            401 | 403 => bail!(
+                "{method} {url}: {} (set EPUBR_WEBDAV_USER / EPUBR_WEBDAV_PASSWORD)",
+                resp.status()
+            ),
+            _ => Ok(resp),
+        }
+    }
+
+    /// Entries of a PROPFIND at `depth` ("0" or "1"); `None` on 404.
+    fn propfind(&self, path: &str, depth: &str) -> Result<Option<Vec<(RemoteFile, bool)>>> {
+        let headers = [("Depth", depth), ("Content-Type", "application/xml")];
+        let mut resp = self.request("PROPFIND", path, &headers, PROPFIND_BODY)?;
+        match resp.status().as_u16() {
+            207 => {}
+            404 => return Ok(None),
+            s => bail!("PROPFIND {path}: HTTP {s}"),
+        }
+        let xml = resp
+            .body_mut()
+            .with_config()
+            .limit(64 << 20)
+            .read_to_string()?;
+        let doc = Document::parse(&xml).context("parse PROPFIND response")?;
+        let text = |r: roxmltree::Node, tag: &str| {
+            r.descendants()
+                .find(|n| n.has_tag_name(tag))
+                .and_then(|n| n.text())
+                .map(str::trim)
+                .map(str::to_string)
+        };
+        let mut out = Vec::new();
+        for r in doc.descendants().filter(|n| n.has_tag_name("response")) {
+            let Some(href) = text(r, "href") else {
+                continue;
+            };
+            // href is an absolute path, or (some servers) a full URL
+            let href = match href.split_once("://") {
+                Some((_, rest)) => rest[rest.find('/').unwrap_or(rest.len())..].to_string(),
+                None => href,
+            };
+            let decoded = percent_decode_str(&href).decode_utf8_lossy();
+            let path = match decoded.trim_end_matches('/') {
+                "" => "/".to_string(),
+                p => p.to_string(),
+            };
+            let dir = r.descendants().any(|n| n.has_tag_name("collection"));
+            let file = RemoteFile {
+                path,
+                size: text(r, "getcontentlength")
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(0),
+                etag: text(r, "getetag").filter(|s| !s.is_empty()),
+            };
+            out.push((file, dir));
+        }
+        Ok(Some(out))
+    }
+}
+
+impl Source for WebDav {
+    fn list(&self, root: &str) -> Result<Vec<RemoteFile>> {
+        let mut files = Vec::new();
+        let mut dirs = vec![root.to_string()];
+        // Hrefs come back decoded and without the trailing slash; a server
+        // that spells a collection differently from how it was asked for
+        // (case, escaping) would otherwise have it listed again forever
+        let key = |p: &str| p.trim_end_matches('/').to_string();
+        let mut visited: HashSet<String> = HashSet::new();
+        while let Some(dir) = dirs.pop() {
+            if !visited.insert(key(&dir)) {
+                continue;
+            }
+            let Some(items) = self.propfind(&dir, "1")? else {
+                bail!("not found: {dir}");
+            };
+            for (item, is_dir) in items {
+                if key(&item.path) == key(&dir) {
+                    if !is_dir {
+                        // The root is a single file
+                        files.push(item);
+                    }
+                } else if is_dir {
+                    if !visited.contains(&key(&item.path)) {
+                        dirs.push(item.path);
+                    }
+                } else {
+                    files.push(item);
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    fn stat(&self, path: &str) -> Result<Option<RemoteFile>> {
+        Ok(self
+            .propfind(path, "0")?
+            .and_then(|items| items.into_iter().find(|(_, dir)| !dir))
+            .map(|(f, _)| f))
+    }
+
+    fn read_range(&self, path: &str, start: u64, len: u64) -> Result<Vec<u8>> {
+        let range = format!("bytes={}-{}", start, start + len - 1);
+        let resp = self.request("GET", path, &[("Range", &range)], "")?;
+        let status = resp.status().as_u16();
+        let mut body = resp.into_body().into_reader();
+        let mut buf = Vec::with_capacity(len as usize);
+        match status {
+            206 => {}
+            // Range ignored: skip to the window in the full body
+            200 => {
+                io::copy(&mut (&mut body).take(start), &mut io::sink())?;
+            }
+            s => bail!("GET {path} ({range}): HTTP {s}"),
+        }
+        body.take(len).read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn Read + '_>> {
+        let resp = self.request("GET", path, &[], "")?;
+        match resp.status().as_u16() {
+            200 => Ok(Box::new(resp.into_body().into_reader())),
+            s => bail!("GET {path}: HTTP {s}"),
+        }
+    }
+}
+
+/// SFTP over one authenticated SSH session.
+struct Sftp {
+    sftp: ssh2::Sftp,
+    _session: Session,
+}
+
+impl Sftp {
+    fn connect(u: &RemoteUri) -> Result<Sftp> {
+        let port = u.port.unwrap_or(22);
+        let host = u.host.trim_start_matches('[').trim_end_matches(']');
+        let tcp =
+            TcpStream::connect((host, port)).with_context(|| format!("connect {host}:{port}"))?;
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.handshake().context("SSH handshake")?;
+        verify_host_key(&session, host, port)?;
+
+        let user = match &u.user {
+            Some(user) => user.clone(),
+            None => env::var("USER").context("no user in the URI and $USER is unset")?,
+        };
+        authenticate(&session, &user)?;
+        Ok(Sftp {
+            sftp: session.sftp().context("start SFTP")?,
+            _session: session,
+        })
+    }
+}
+
+/// Accept only hosts whose key is already in `~/.ssh/known_hosts`.
+fn verify_host_key(session: &Session, host: &str, port: u16) -> Result<()> {
+    let (key, _) = session
+        .host_key()
+        .ok_or_else(|| anyhow!("{host}: no host key"))?;
+    let mut known = session.known_hosts()?;
+    let file = home().join(".ssh/known_hosts");
+    // A missing file just means no host is known
+    let _ = known.read_file(&file, KnownHostFileKind::OpenSSH);
+    match known.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::NotFound => bail!(
+            "{host}: host key not in {} (connect once with ssh to accept it)",
+            file.display()
+        ),
+        CheckResult::Mismatch => bail!("{host}: host key does NOT match {}", file.display()),
+        CheckResult::Failure => bail!("{host}: host key check failed"),
+    }
+}
+
+/// Password (`EPUBR_SFTP_PASSWORD`), key file (`EPUBR_SFTP_KEY`), then the
+/// ssh agent, then the default key files.
+fn authenticate(session: &Session, user: &str) -> Result<()> {
+    if let Ok(pass) = env::var("EPUBR_SFTP_PASSWORD") {
+        session
+            .userauth_password(user, &pass)
+            .context("SFTP password authentication")?;
+        return Ok(());
+    }
+    if let Ok(key) = env::var("EPUBR_SFTP_KEY") {
+        session
+            .userauth_pubkey_file(user, None, Path::new(&key), None)
+            .with_context(|| format!("SFTP key authentication ({key})"))?;
+        return Ok(());
+    }
+    if session.userauth_agent(user).is_ok() && session.authenticated() {
+        return Ok(());
+    }
+    for key in ["id_ed25519", "id_rsa"] {
+        let key = home().join(".ssh").join(key);
+        if key.is_file()
+            && session.userauth_pubkey_file(user, None, &key, None).is_ok()
+            && session.authenticated()
+        {
+            return Ok(());
+        }
+    }
+    bail!(
+        "SFTP authentication failed for {user} (agent, ~/.ssh keys; or set EPUBR_SFTP_KEY / EPUBR_SFTP_PASSWORD)"
+    )
+}
+
+fn home() -> PathBuf {
+    env::var_os("HOME").map(PathBuf::from).unwrap_or_default()
+}
+
+fn sftp_file(path: String, st: &ssh2::FileStat) -> RemoteFile {
+    RemoteFile {
+        path,
+        size: st.size.unwrap_or(0),
+        etag: st.mtime.map(|t| format!("mtime:{t}")),
+    }
+}
+
+impl Source for Sftp {
+    fn list(&self, root: &str) -> Result<Vec<RemoteFile>> {
+        let st = self
+            .sftp
+            .stat(Path::new(root))
+            .with_context(|| format!("stat {root}"))?;
+        if !st.is_dir() {
+            return Ok(vec![sftp_file(root.to_string(), &st)]);
+        }
+        let mut files = Vec::new();
+        let mut dirs = vec![PathBuf::from(root)];
+        while let Some(dir) = dirs.pop() {
+            let entries = match self.sftp.readdir(&dir) {
+                Ok(e) => e,
+                Err(e) => {
+                    warn!("sftp: readdir {}: {}", dir.display(), e);
+                    report::file_error(dir.display(), format!("readdir: {e}"));
+                    continue;
+                }
+            };
+            for (p, st) in entries {
+                if st.is_dir() {
+                    dirs.push(p);
+                } else if st.is_file() {
+                    files.push(sftp_file(p.to_string_lossy().into_owned(), &st));
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    fn stat(&self, path: &str) -> Result<Option<RemoteFile>> {
+        match self.sftp.stat(Path::new(path)) {
+            Ok(st) if st.is_file() => Ok(Some(sftp_file(path.to_string(), &st))),
+            Ok(_) => Ok(None),
+            // SSH_FX_NO_SUCH_FILE
+            Err(e) if e.code() == ErrorCode::SFTP(2) => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("stat {path}")),
+        }
+    }
+
+    fn read_range(&self, path: &str, start: u64, len: u64) -> Result<Vec<u8>> {
+        let mut f = self.sftp.open(Path::new(path))?;
+        f.seek(SeekFrom::Start(start))?;
+        let mut buf = Vec::with_capacity(len as usize);
+        f.take(len).read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn Read + '_>> {
+        let f = self
+            .sftp
+            .open(Path::new(path))
+            .with_context(|| format!("open {path}"))?;
+        Ok(Box::new(BufReader::new(f)))
+    }
+}
@@ -0,0 +1,298 @@
+//! Result object for `--output json`: one JSON document per run on stdout
+//! (logs stay on stderr).
+//!
+//! The counts are not tallied by each command: `Changes` compares the
+//! entries before and after the run (also the diff `--dry-run` prints, which
+//! adds the named roots).
+//! An entry is recognised by its `full_path`, preferring a record on the
+//! same volume and root, with `date_found` telling a replaced record from the
+//! fresh one appended for the same path; an entry `organize` moved is found
+//! by its `date_found` and `xxhash`. Per-file failures are
+//! recorded with `file_error` wherever they happen (often inside rayon
+//! jobs), next to the existing log line. Commands with listings of their own
+//! (`validate`, `dedupe`, …) add them under `details`.
+
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::commands::export::{FIELDS, field_value};
+use crate::model::BookEntry;
+
+static ERRORS: Mutex<Vec<FileError>> = Mutex::new(Vec::new());
+
+/// Record a per-file failure for the result object (the caller logs it).
+pub fn file_error(path: impl Display, err: impl Display) {
+    let e = FileError {
+        path: path.to_string(),
+        error: format!("{err:#}"),
+    };
+    ERRORS.lock().unwrap_or_else(|e| e.into_inner()).push(e);
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileError {
+    pub path: String,
+    pub error: String,
+}
+
+/// Entry-level difference between the DB before and after a run.
+#[derive(Debug, Default)]
+pub struct Changes<'a> {
+    pub added: Vec<&'a BookEntry>,
+    /// (before, after) of entries edited in place; a replaced entry pairs
+    /// its old record with the fresh one appended for the same path
+    pub changed: Vec<(&'a BookEntry, &'a BookEntry)>,
+    pub removed: Vec<&'a BookEntry>,
+    /// (name, before, after) of named roots that were set, moved or removed
+    pub roots: Vec<(&'a str, Option<&'a PathBuf>, Option<&'a PathBuf>)>,
+}
+
+impl<'a> Changes<'a> {
+    pub fn between(before: &'a [BookEntry], after: &'a [BookEntry]) -> Changes<'a> {
+        let mut by_path: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, a) in after.iter().enumerate() {
+            by_path.entry(a.full_path.as_str()).or_default().push(i);
+        }
+        let mut taken = vec![false; after.len()];
+        let mut pairs: Vec<(&BookEntry, Option<usize>)> = Vec::with_capacity(before.len());
+        for b in before {
+            let same = |a: &BookEntry| {
+                (
+                    a.volume == b.volume,
+                    a.root == b.root,
+                    a.date_found == b.date_found,
+                )
+            };
+            // A stale record stays stale. First best candidate, so equal
+            // ones pair up in order
+            let best = by_path.get(b.full_path.as_str()).and_then(|ids| {
+                ids.iter()
+                    .copied()
+                    .filter(|&i| !taken[i] && (after[i].stale || !b.stale))
+                    .rev()
+                    .max_by_key(|&i| same(&after[i]))
+            });
+            if let Some(i) = best {
+                taken[i] = true;
+            }
+            pairs.push((b, best));
+        }
+        // Moved entries (`organize`): same record under a new path
+        let mut by_date: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, a) in after.iter().enumerate().filter(|&(i, _)| !taken[i]) {
+            by_date.entry(a.date_found.as_str()).or_default().push(i);
+        }
+        for (b, slot) in pairs.iter_mut().filter(|(_, s)| s.is_none()) {
+            *slot = by_date.get(b.date_found.as_str()).and_then(|ids| {
+                ids.iter()
+                    .copied()
+                    .find(|&i| !taken[i] && b.xxhash.is_some() && after[i].xxhash == b.xxhash)
+            });
+            if let Some(i) = *slot {
+                taken[i] = true;
+            }
+        }
+
+        let mut c = Changes::default();
+        let mut stale = Vec::new();
+        for (b, slot) in pairs {
+            let Some(i) = slot else {
+                c.removed.push(b);
+                continue;
+            };
+            let a = &after[i];
+            if a == b {
+                continue;
+            }
+            if a.stale && !b.stale {
+                stale.push((b, a));
+            } else {
+                c.changed.push((b, a));
+            }
+        }
+        // Unpaired: fresh records of replaced entries are not new books
+        let mut appended: Vec<Option<&BookEntry>> = after
+            .iter()
+            .zip(&taken)
+            .map(|(a, &t)| (!t).then_some(a))
+            .collect();
+        for (b, a) in stale {
+            let fresh = appended
+                .iter_mut()
+                .find(|n| n.is_some_and(|n| n.full_path == b.full_path))
+                .and_then(Option::take);
+            c.changed.push((b, fresh.unwrap_or(a)));
+        }
+        c.added = appended.into_iter().flatten().collect();
+        c
+    }
+
+    /// Add the named roots that differ between `before` and `after`.
+    pub fn with_roots(
+        mut self,
+        before: &'a BTreeMap<String, PathBuf>,
+        after: &'a BTreeMap<String, PathBuf>,
+    ) -> Changes<'a> {
+        let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+        self.roots = names
+            .into_iter()
+            .map(|n| (n.as_str(), before.get(n), after.get(n)))
+            .filter(|(_, b, a)| b != a)
+            .collect();
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.changed.is_empty()
+            && self.removed.is_empty()
+            && self.roots.is_empty()
+    }
+
+    /// Print the diff for `--dry-run`: `+ path`, `~ path: fields` (`~ old ->
+    /// new: fields` for a moved entry), `- path`,
+    /// then roots as `+ root NAME = DIR`, `~ root NAME: OLD -> NEW`, `- root NAME`.
+    pub fn print(&self) {
+        if self.is_empty() {
+            println!("(no changes)");
+        }
+        for e in &self.added {
+            println!("+ {}", e.full_path);
+        }
+        for (b, a) in &self.changed {
+            let path = if b.full_path == a.full_path {
+                b.full_path.clone()
+            } else {
+                format!("{} -> {}", b.full_path, a.full_path)
+            };
+            println!("~ {path}: {}", changed_fields(b, a).join(", "));
+        }
+        for e in &self.removed {
+            println!("- {}", e.full_path);
+        }
+        for (name, b, a) in &self.roots {
+            match (b, a) {
+                (None, Some(a)) => println!("+ root {name} = {}", a.display()),
+                (Some(b), Some(a)) => {
+                    println!("~ root {name}: {} -> {}", b.display(), a.display())
+                }
+                _ => println!("- root {name}"),
+            }
+        }
+    }
+
+    /// The diff as JSON, for `details.diff`.
+    pub fn to_json(&self) -> Value {
+        let paths = |v: &[&BookEntry]| v.iter().map(|e| e.full_path.clone()).collect::<Vec<_>>();
+        let changed: Vec<Value> = self
+            .changed
+            .iter()
+            .map(|(b, a)| json!({ "path": b.full_path, "fields": changed_fields(b, a) }))
+            .collect();
+        let roots: Vec<Value> = self
+            .roots
+            .iter()
+            .map(|(name, b, a)| json!({ "name": name, "before": b, "after": a }))
+            .collect();
+        json!({
+            "added": paths(&self.added),
+            "changed": changed,
+            "removed": paths(&self.removed),
+            "roots": roots,
+        })
+    }
+}
+
+/// Names of the fields that differ between two records of one book, as
+/// `export` names them (`id` and `date_found` differ whenever a record was
+/// replaced, so they are left out).
+pub fn changed_fields(b: &BookEntry, a: &BookEntry) -> Vec<&'static str> {
+    let mut fields: Vec<&'static str> = FIELDS
+        .iter()
+        .copied()
+        .filter(|f| !matches!(*f, "id" | "date_found"))
+        .filter(|f| field_value(b, f) != field_value(a, f))
+        .collect();
+    if b.other_metadata != a.other_metadata {
+        fields.push("other_metadata");
+    }
+    fields
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub command: &'static str,
+    pub ok: bool,
+    /// Why the command failed as a whole
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Entries in the DB afterwards
+    pub entries: usize,
+    pub added: usize,
+    /// Changed in place, or replaced by a fresh record (old one stale)
+    pub updated: usize,
+    /// Newly marked missing
+    pub missing: usize,
+    /// Removed from the DB
+    pub pruned: usize,
+    /// Files with at least one entry in `errors`
+    pub failed: usize,
+    pub errors: Vec<FileError>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub details: Map<String, Value>,
+    /// `--dry-run`: the DB was not saved
+    pub dry_run: bool,
+    /// `--output json` was given; commands print listings only without it
+    #[serde(skip)]
+    pub json: bool,
+}
+
+impl Report {
+    pub fn new(command: &'static str, json: bool) -> Report {
+        Report {
+            command,
+            json,
+            ..Default::default()
+        }
+    }
+
+    /// Add a command-specific field under `details`.
+    pub fn detail(&mut self, key: &str, value: impl Serialize) {
+        let v = serde_json::to_value(value).unwrap_or(Value::Null);
+        self.details.insert(key.to_string(), v);
+    }
+
+    /// Count what changed between `before` and `after`.
+    pub fn tally(&mut self, changes: &Changes) {
+        self.added += changes.added.len();
+        self.pruned += changes.removed.len();
+        for (b, a) in &changes.changed {
+            if a.missing && !b.missing {
+                self.missing += 1;
+            } else {
+                self.updated += 1;
+            }
+        }
+    }
+
+    /// Print the object on stdout, taking the per-file errors recorded so far.
+    pub fn print(mut self, result: &anyhow::Result<()>) {
+        self.errors = std::mem::take(&mut *ERRORS.lock().unwrap_or_else(|e| e.into_inner()));
+        self.failed = self
+            .errors
+            .iter()
+            .map(|e| e.path.as_str())
+            .collect::<BTreeSet<_>>()
+            .len();
+        self.ok = result.is_ok();
+        self.error = result.as_ref().err().map(|e| format!("{e:#}"));
+        match serde_json::to_string_pretty(&self) {
+            Ok(s) => println!("{s}"),
+            Err(e) => eprintln!("report: {e}"),
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Named library roots: entries under a root store (root name, relative path)
+//! and get their `full_path`/`uri_path` recomputed from the root's current
+//! mountpoint, so moving a disk only needs a single root update.
+
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::model::{BookEntry, BooksDb};
+use crate::util::file_uri;
+
+/// Absolute, canonical form of a root directory (as-is if it cannot be resolved).
+pub fn normalize(dir: &Path) -> PathBuf {
+    dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf())
+}
+
+/// Add or re-point a named root, and put the entries already under its
+/// directory on it. Returns true if the DB changed.
+pub fn set_root(db: &mut BooksDb, name: &str, dir: &Path) -> Result<bool> {
+    if name.is_empty() || name.contains('/') {
+        bail!("invalid root name {name:?} (must be non-empty, without '/')");
+    }
+    let dir = normalize(dir);
+    if !dir.is_absolute() {
+        bail!("root {name} must be an absolute path: {}", dir.display());
+    }
+    if db.roots.get(name) == Some(&dir) {
+        return Ok(false);
+    }
+    match db.roots.insert(name.to_string(), dir.clone()) {
+        Some(old) => info!("root {}: {} → {}", name, old.display(), dir.display()),
+        None => info!("root {}: added at {}", name, dir.display()),
+    }
+    resolve_paths(db);
+    // Entries indexed before the root existed (or before it moved here) join it
+    let mut joined = 0usize;
+    for e in db.books.iter_mut().filter(|e| !e.stale) {
+        if e.root.as_deref() != Some(name) {
+            assign(&db.roots, e);
+            joined += usize::from(e.root.as_deref() == Some(name));
+        }
+    }
+    if joined > 0 {
+        info!(
+            "root {}: {} existing entr(y/ies) moved under it",
+            name, joined
+        );
+    }
+    Ok(true)
+}
+
+/// Look up a root's directory by name.
+pub fn root_dir<'a>(roots: &'a BTreeMap<String, PathBuf>, name: &str) -> Result<&'a PathBuf> {
+    roots
+        .get(name)
+        .with_context(|| format!("unknown root {name:?}"))
+}
+
+/// Find the (deepest) named root containing `p`, returning (name, relative path).
+pub fn locate(roots: &BTreeMap<String, PathBuf>, p: &Path) -> Option<(String, String)> {
+    let abs = if p.is_absolute() {
+        p.to_path_buf()
+    } else {
+        normalize(p)
+    };
+    roots
+        .iter()
+        .filter_map(|(name, dir)| {
+            abs.strip_prefix(dir)
+                .ok()
+                .map(|rel| (dir.components().count(), name, rel_string(rel)))
+        })
+        .max_by_key(|(depth, _, _)| *depth)
+        .map(|(_, name, rel)| (name.clone(), rel))
+}
+
+/// Tag an entry with its root (if any) and rewrite its paths in canonical form.
+pub fn assign(roots: &BTreeMap<String, PathBuf>, e: &mut BookEntry) {
+    if let Some((name, rel)) = locate(roots, Path::new(&e.full_path)) {
+        e.root = Some(name);
+        e.rel_path = Some(rel);
+        resolve_entry(roots, e);
+    }
+}
+
+/// Point an entry at a new location (after a rename/move), re-deriving its
+/// file name, URI and root membership.
+pub fn relocate(roots: &BTreeMap<String, PathBuf>, e: &mut BookEntry, p: &Path) {
+    e.full_path = p.to_string_lossy().to_string();
+    e.uri_path = file_uri(p);
+    e.filename = p
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .to_string();
+    e.root = None;
+    e.rel_path = None;
+    assign(roots, e);
+}
+
+/// Recompute `full_path`/`uri_path` for every rooted entry from the current roots.
+pub fn resolve_paths(db: &mut BooksDb) {
+    for e in db.books.iter_mut() {
+        resolve_entry(&db.roots, e);
+    }
+}
+
+/// Recompute one entry's `full_path`/`uri_path` from its root, if it has one.
+pub fn resolve_entry(roots: &BTreeMap<String, PathBuf>, e: &mut BookEntry) {
+    let (Some(name), Some(rel)) = (&e.root, &e.rel_path) else {
+        return;
+    };
+    if let Some(dir) = roots.get(name) {
+        let p = dir.join(rel);
+        e.full_path = p.to_string_lossy().to_string();
+        e.uri_path = file_uri(&p);
+    }
+}
+
+/// Relative path with '/' separators, so DBs move between platforms.
+fn rel_string(rel: &Path) -> String {
+    rel.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -0,0 +1,341 @@
+//! S3-compatible object storage (`s3://bucket/prefix`) as a remote source.
+//!
+//! Objects are listed with ListObjectsV2, read with ranged GETs and checked
+//! with HEAD; keys map to paths as `/<key>`, so "directories" are just key
+//! prefixes. Requests are signed with AWS Signature V4.
+//!
+//! Configuration comes from the usual AWS variables:
+//! `AWS_ACCESS_KEY_ID` / `AWS_SECRET_ACCESS_KEY` (+ `AWS_SESSION_TOKEN`;
+//! unsigned requests when unset), `AWS_REGION` / `AWS_DEFAULT_REGION`
+//! (default `us-east-1`), and `EPUBR_S3_ENDPOINT` or `AWS_ENDPOINT_URL` for
+//! other servers (MinIO, Ceph, …), which are addressed path-style. Without
+//! an endpoint, AWS is addressed virtual-host style.
+
+use anyhow::{Context, Result, bail};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use roxmltree::Document;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::io::{self, Read};
+use ureq::Agent;
+use ureq::http::{Request, Response};
+
+use crate::remote::{self, RemoteFile, RemoteUri, Source};
+
+/// SigV4 escaping: everything but unreserved characters…
+const QUERY_ESCAPE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+/// …and `/` stays literal in object keys.
+const KEY_ESCAPE: &AsciiSet = &QUERY_ESCAPE.remove(b'/');
+
+/// SHA-256 of an empty body (we never send one).
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+struct Credentials {
+    key_id: String,
+    secret: String,
+    token: Option<String>,
+}
+
+/// One bucket on one S3 endpoint.
+pub struct S3 {
+    agent: Agent,
+    /// `http(s)://host[:port]`
+    endpoint: String,
+    /// `Host` header (and signed host)
+    host: String,
+    /// Path prefix before the key: `/bucket` (path-style) or empty
+    bucket_path: String,
+    bucket: String,
+    region: String,
+    creds: Option<Credentials>,
+}
+
+impl S3 {
+    pub fn connect(u: &RemoteUri) -> Result<S3> {
+        if u.user.is_some() || u.port.is_some() {
+            bail!("s3:// URIs take no user or port; set EPUBR_S3_ENDPOINT for other servers");
+        }
+        let region = env::var("AWS_REGION")
+            .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| "us-east-1".into());
+        let custom = env::var("EPUBR_S3_ENDPOINT")
+            .or_else(|_| env::var("AWS_ENDPOINT_URL"))
+            .ok()
+            .filter(|s| !s.is_empty());
+        let (endpoint, bucket_path) = match custom {
+            Some(ep) => (ep.trim_end_matches('/').to_string(), format!("/{}", u.host)),
+            None => (
+                format!("https://{}.s3.{region}.amazonaws.com", u.host),
+                String::new(),
+            ),
+        };
+        let Some((_, host)) = endpoint.split_once("://") else {
+            bail!("S3 endpoint needs a scheme (http:// or https://): {endpoint}");
+        };
+        let host = host.to_string();
+        let creds = match (
+            env::var("AWS_ACCESS_KEY_ID"),
+            env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            (Ok(key_id), Ok(secret)) => Some(Credentials {
+                key_id,
+                secret,
+                token: env::var("AWS_SESSION_TOKEN").ok(),
+            }),
+            _ => None,
+        };
+        Ok(S3 {
+            agent: remote::http_agent(),
+            endpoint,
+            host,
+            bucket_path,
+            bucket: u.host.clone(),
+            region,
+            creds,
+        })
+    }
+
+    /// Signed request for an object `key` (or the bucket, when empty).
+    fn request(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, &str)],
+    ) -> Result<Response<ureq::Body>> {
+        let path = format!(
+            "{}/{}",
+            self.bucket_path,
+            utf8_percent_encode(key, KEY_ESCAPE)
+        );
+        let mut query: Vec<String> = query
+            .iter()
+            .map(|(k, v)| {
+                format!(
+                    "{}={}",
+                    utf8_percent_encode(k, QUERY_ESCAPE),
+                    utf8_percent_encode(v, QUERY_ESCAPE)
+                )
+            })
+            .collect();
+        query.sort();
+        let query = query.join("&");
+        let url = match query.as_str() {
+            "" => format!("{}{path}", self.endpoint),
+            q => format!("{}{path}?{q}", self.endpoint),
+        };
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let mut signed = vec![
+            ("host", self.host.clone()),
+            ("x-amz-content-sha256", EMPTY_SHA256.to_string()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        let mut auth = None;
+        if let Some(c) = &self.creds {
+            if let Some(t) = &c.token {
+                signed.push(("x-amz-security-token", t.clone()));
+            }
+            let names = signed.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(";");
+            let canonical = format!(
+                "{method}\n{path}\n{query}\n{}\n{names}\n{EMPTY_SHA256}",
+                signed
+                    .iter()
+                    .map(|(k, v)| format!("{k}:{v}\n"))
+                    .collect::<String>()
+            );
+            let date = now.format("%Y%m%d").to_string();
+            let scope = format!("{date}/{}/s3/aws4_request", self.region);
+            let to_sign = format!(
+                "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+                hex(&Sha256::digest(canonical.as_bytes()))
+            );
+            let mut key = hmac(format!("AWS4{}", c.secret).as_bytes(), date.as_bytes());
+            for part in [self.region.as_str(), "s3", "aws4_request"] {
+                key = hmac(&key, part.as_bytes());
+            }
+            auth = Some(format!(
+                "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={names}, Signature={}",
+                c.key_id,
+                hex(&hmac(&key, to_sign.as_bytes()))
+            ));
+        }
+
+        let build = || {
+            let mut req = Request::builder().method(method).uri(&url);
+            for (k, v) in &signed {
+                req = req.header(*k, v);
+            }
+            if let Some(a) = &auth {
+                req = req.header("Authorization", a);
+            }
+            for (k, v) in headers {
+                req = req.header(*k, *v);
+            }
+            req.body("")
+        };
+        let mut resp =
+            remote::send(&self.agent, build).with_context(|| format!("{method} {url}"))?;
+        match resp.status().as_u16() {
+            200..=299 | 404 | 416 => Ok(resp),
+            s => {
+                let body = resp.body_mut().read_to_string().unwrap_or_default();
+                let hint = if (s == 401 || s == 403) && self.creds.is_none() {
+                    " (set AWS_ACCESS_KEY_ID / AWS_SECRET_ACCESS_KEY)"
+                } else {
+                    ""
+                };
+                bail!("{method} {url}: HTTP {s}{}{hint}", error_detail(&body))
+            }
+        }
+    }
+}
+
+impl Source for S3 {
+    fn list(&self, root: &str) -> Result<Vec<RemoteFile>> {
+        let key = root.trim_start_matches('/');
+        let prefix = if key.is_empty() {
+            String::new()
+        } else {
+            format!("{key}/")
+        };
+        let mut files = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix.as_str())];
+            if let Some(t) = &token {
+                query.push(("continuation-token", t));
+            }
+            let mut resp = self.request("GET", "", &query, &[])?;
+            if resp.status().as_u16() == 404 {
+                bail!("no such bucket: {}", self.bucket);
+            }
+            let xml = resp
+                .body_mut()
+                .with_config()
+                .limit(64 << 20)
+                .read_to_string()?;
+            let doc = Document::parse(&xml).context("parse ListObjectsV2 response")?;
+            let text = |n: roxmltree::Node, tag: &str| {
+                n.children()
+                    .find(|c| c.has_tag_name(tag))
+                    .and_then(|c| c.text())
+                    .map(str::to_string)
+            };
+            for c in doc
+                .root_element()
+                .children()
+                .filter(|n| n.has_tag_name("Contents"))
+            {
+                let Some(key) = text(c, "Key") else {
+                    continue;
+                };
+                // Zero-byte "folder" markers
+                if key.ends_with('/') {
+                    continue;
+                }
+                files.push(RemoteFile {
+                    path: format!("/{key}"),
+                    size: text(c, "Size").and_then(|s| s.parse().ok()).unwrap_or(0),
+                    etag: text(c, "ETag").filter(|s| !s.is_empty()),
+                });
+            }
+            token = text(doc.root_element(), "NextContinuationToken");
+            let truncated = text(doc.root_element(), "IsTruncated").as_deref() == Some("true");
+            if !truncated || token.is_none() {
+                break;
+            }
+        }
+        if files.is_empty() && !key.is_empty() {
+            // The prefix may name a single object
+            if let Some(f) = self.stat(root)? {
+                files.push(f);
+            }
+        }
+        Ok(files)
+    }
+
+    fn stat(&self, path: &str) -> Result<Option<RemoteFile>> {
+        let resp = self.request("HEAD", path.trim_start_matches('/'), &[], &[])?;
+        if resp.status().as_u16() == 404 {
+            return Ok(None);
+        }
+        let header = |name: &str| {
+            resp.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        Ok(Some(RemoteFile {
+            path: path.to_string(),
+            size: header("content-length")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+            etag: header("etag"),
+        }))
+    }
+
+    fn read_range(&self, path: &str, start: u64, len: u64) -> Result<Vec<u8>> {
+        let range = format!("bytes={}-{}", start, start + len - 1);
+        let key = path.trim_start_matches('/');
+        let resp = self.request("GET", key, &[], &[("Range", &range)])?;
+        let status = resp.status().as_u16();
+        let mut body = resp.into_body().into_reader();
+        let mut buf = Vec::with_capacity(len as usize);
+        match status {
+            206 => {}
+            // Range ignored: skip to the window in the full body
+            200 => {
+                io::copy(&mut (&mut body).take(start), &mut io::sink())?;
+            }
+            // Past the end
+            416 => return Ok(buf),
+            s => bail!("GET {path} ({range}): HTTP {s}"),
+        }
+        body.take(len).read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn Read + '_>> {
+        let resp = self.request("GET", path.trim_start_matches('/'), &[], &[])?;
+        match resp.status().as_u16() {
+            200 => Ok(Box::new(resp.into_body().into_reader())),
+            s => bail!("GET {path}: HTTP {s}"),
+        }
+    }
+}
+
+/// ": Code: Message" from an S3 error document, if the body is one.
+fn error_detail(body: &str) -> String {
+    let Ok(doc) = Document::parse(body) else {
+        return String::new();
+    };
+    let get = |tag: &str| {
+        doc.descendants()
+            .find(|n| n.has_tag_name(tag))
+            .and_then(|n| n.text())
+            .unwrap_or_default()
+    };
+    match (get("Code"), get("Message")) {
+        ("", _) => String::new(),
+        (code, "") => format!(": {code}"),
+        (code, msg) => format!(": {code}: {msg}"),
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
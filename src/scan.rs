@@ -1,26 +1,241 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use clap::Args;
+use ignore::WalkBuilder;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::{Override, OverrideBuilder};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use walkdir::{DirEntry, WalkDir};
+use tracing::warn;
 
-fn is_epub(entry: &DirEntry) -> bool {
-    entry
-        .path()
-        .extension()
-        .map(|e| e.eq_ignore_ascii_case("epub") || e.eq_ignore_ascii_case("pdf"))
-        .unwrap_or(false)
+use crate::container::is_exploded_epub;
+use crate::model::FileFormat;
+use crate::report;
+
+/// Per-directory ignore file, gitignore syntax, picked up during the walk.
+pub const IGNORE_FILENAME: &str = ".epubrignore";
+
+/// Knobs for `gather_epubs`, shared as CLI flags by `load` and `watch`.
+/// `Default` walks everything below the root.
+#[derive(Debug, Clone, Default, Args)]
+pub struct ScanOpts {
+    /// Follow symlinks when walking directories
+    #[arg(long)]
+    pub follow_symlinks: bool,
+
+    /// Only index files matching this glob (relative to the root; repeatable)
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Skip files/directories matching this glob (relative to the root; repeatable)
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Maximum directory depth below the root (root = 0)
+    #[arg(long, value_name = "N")]
+    pub max_depth: Option<usize>,
+
+    /// Skip dot-files and dot-directories (.Trash-1000, .caltrash, .stversions, ...)
+    #[arg(long)]
+    pub skip_hidden: bool,
+
+    /// Do not cross filesystem boundaries (mount points)
+    #[arg(long)]
+    pub one_file_system: bool,
+}
+
+/// True if the path has an extension we index (see `FileFormat::from_path`).
+pub fn is_book_path(p: &Path) -> bool {
+    FileFormat::from_path(p).is_some()
 }
 
-pub fn gather_epubs(root: &Path, follow_symlinks: bool) -> Result<Vec<PathBuf>> {
-    let mut v = Vec::new();
-    let mut wd = WalkDir::new(root).follow_links(follow_symlinks).into_iter();
+/// Include globs whitelist, exclude globs are negated (gitignore override semantics).
+fn build_overrides(root: &Path, opts: &ScanOpts) -> Result<Override> {
+    let mut ov = OverrideBuilder::new(root);
+    for g in &opts.include {
+        ov.add(g)
+            .with_context(|| format!("invalid --include glob: {g}"))?;
+    }
+    for g in &opts.exclude {
+        ov.add(&format!("!{g}"))
+            .with_context(|| format!("invalid --exclude glob: {g}"))?;
+    }
+    Ok(ov.build()?)
+}
 
-    while let Some(Ok(entry)) = wd.next() {
-        if entry.file_type().is_dir() {
+/// Book files, plus exploded EPUB directories (taken whole, not descended into).
+pub fn gather_epubs(root: &Path, opts: &ScanOpts) -> Result<Vec<PathBuf>> {
+    gather_files(root, opts, |p, is_dir| {
+        if is_dir {
+            is_exploded_epub(p)
+        } else {
+            is_book_path(p)
+        }
+    })
+}
+
+/// Walk like `gather_epubs`, keeping the paths `keep(path, is_dir)` accepts.
+/// A kept directory is one book: nothing below it is visited.
+pub fn gather_files(
+    root: &Path,
+    opts: &ScanOpts,
+    keep: impl Fn(&Path, bool) -> bool,
+) -> Result<Vec<PathBuf>> {
+    let walker = WalkBuilder::new(root)
+        .standard_filters(false) // no .gitignore/.ignore; only our own ignore file
+        .add_custom_ignore_filename(IGNORE_FILENAME)
+        .hidden(opts.skip_hidden)
+        .follow_links(opts.follow_symlinks)
+        .max_depth(opts.max_depth)
+        .same_file_system(opts.one_file_system)
+        .overrides(build_overrides(root, opts)?)
+        .build();
+
+    let mut v: Vec<PathBuf> = Vec::new();
+    // The walk is depth-first, so a kept directory's contents follow it
+    let mut inside: Option<PathBuf> = None;
+    for entry in walker {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(e) => {
+                warn!("scan: {}", e);
+                report::file_error(error_path(&e).unwrap_or(root).display(), &e);
+                continue;
+            }
+        };
+        if inside.as_ref().is_some_and(|d| entry.path().starts_with(d)) {
+            continue;
+        }
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        if !keep(entry.path(), is_dir) {
             continue;
         }
-        if is_epub(&entry) {
-            v.push(entry.into_path());
+        if is_dir {
+            inside = Some(entry.path().to_path_buf());
         }
+        v.push(entry.into_path());
     }
     Ok(v)
 }
+
+/// The path a walk error is about, if it names one.
+fn error_path(e: &ignore::Error) -> Option<&Path> {
+    match e {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::Loop { child, .. } => Some(child),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            error_path(err)
+        }
+        _ => None,
+    }
+}
+
+/// The walk's rules for single paths reported outside a walk (e.g. by
+/// `watch`): `--include`/`--exclude`/`--max-depth`/`--skip-hidden` and the
+/// `.epubrignore` files under the root, read when the filter is built.
+/// As in the walk, a matching glob overrides the ignore files, and the
+/// deepest ignore file with a matching rule decides.
+#[derive(Debug)]
+pub struct PathFilter {
+    root: PathBuf,
+    overrides: Override,
+    /// Directory → its parsed `.epubrignore`
+    ignores: HashMap<PathBuf, Gitignore>,
+    max_depth: Option<usize>,
+    skip_hidden: bool,
+}
+
+impl PathFilter {
+    pub fn new(root: &Path, opts: &ScanOpts) -> Result<Self> {
+        let mut f = Self::for_remote(root, opts)?;
+        f.ignores = load_ignore_files(root, opts);
+        Ok(f)
+    }
+
+    /// Globs, depth and hidden rules only: `root` is a server path, and
+    /// ignore files are not looked for there.
+    pub fn for_remote(root: &Path, opts: &ScanOpts) -> Result<Self> {
+        Ok(Self {
+            root: root.to_path_buf(),
+            overrides: build_overrides(root, opts)?,
+            ignores: HashMap::new(),
+            max_depth: opts.max_depth,
+            skip_hidden: opts.skip_hidden,
+        })
+    }
+
+    /// True if `p` (a file below the root) would have been picked up by the walk.
+    pub fn admits(&self, p: &Path) -> bool {
+        let Ok(rel) = p.strip_prefix(&self.root) else {
+            return false;
+        };
+        if self.max_depth.is_some_and(|d| rel.components().count() > d) {
+            return false;
+        }
+        if self.skip_hidden
+            && rel
+                .components()
+                .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+        {
+            return false;
+        }
+        // An excluded ancestor directory prunes everything below it.
+        let dirs_ok = p
+            .ancestors()
+            .skip(1)
+            .take_while(|a| *a != self.root)
+            .all(|a| !self.ignored(a, true));
+        dirs_ok && !self.ignored(p, false)
+    }
+
+    fn ignored(&self, p: &Path, is_dir: bool) -> bool {
+        let m = self.overrides.matched(p, is_dir);
+        if !m.is_none() {
+            return m.is_ignore();
+        }
+        for dir in p.ancestors().skip(1) {
+            if let Some(gi) = self.ignores.get(dir) {
+                let m = gi.matched(p, is_dir);
+                if !m.is_none() {
+                    return m.is_ignore();
+                }
+            }
+            if dir == self.root {
+                break;
+            }
+        }
+        false
+    }
+}
+
+/// Parse every `.epubrignore` the walk below `root` would read. A file that
+/// fails to parse is logged and its valid lines kept, as the walk does.
+fn load_ignore_files(root: &Path, opts: &ScanOpts) -> HashMap<PathBuf, Gitignore> {
+    let walker = WalkBuilder::new(root)
+        .standard_filters(false)
+        .add_custom_ignore_filename(IGNORE_FILENAME)
+        .hidden(opts.skip_hidden)
+        .follow_links(opts.follow_symlinks)
+        .max_depth(opts.max_depth)
+        .same_file_system(opts.one_file_system)
+        .build();
+    let mut out = HashMap::new();
+    for entry in walker.flatten() {
+        if entry.file_name() != IGNORE_FILENAME || !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let Some(dir) = entry.path().parent() else {
+            continue;
+        };
+        let mut b = GitignoreBuilder::new(dir);
+        if let Some(e) = b.add(entry.path()) {
+            warn!("scan: {}", e);
+        }
+        match b.build() {
+            Ok(gi) => {
+                out.insert(dir.to_path_buf(), gi);
+            }
+            Err(e) => warn!("scan: {}: {}", entry.path().display(), e),
+        }
+    }
+    out
+}
@@ -1,5 +1,5 @@
 use chrono::Utc;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use url::Url;
 
 pub fn now_iso8601() -> String {
@@ -12,3 +12,25 @@ pub fn file_uri(p: &Path) -> String {
         .map(|u| u.to_string())
         .unwrap_or_default()
 }
+
+/// Absolute form of `p` (resolving symlinks when it exists).
+pub fn absolute(p: &Path) -> PathBuf {
+    p.canonicalize()
+        .or_else(|_| std::path::absolute(p))
+        .unwrap_or_else(|_| p.to_path_buf())
+}
+
+/// Relative path from directory `from` to `to` (both absolute), e.g. for links.
+pub fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut rel = PathBuf::new();
+    for _ in common..from.len() {
+        rel.push("..");
+    }
+    for c in &to[common..] {
+        rel.push(c);
+    }
+    rel
+}
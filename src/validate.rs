@@ -0,0 +1,265 @@
+//! EPUB structural checks — the breakage that makes readers choke, not a
+//! full epubcheck (no schema or accessibility rules):
+//! - `mimetype` is the first entry, stored, and reads `application/epub+zip`
+//!   (for an exploded directory: exists and reads `application/epub+zip`)
+//! - `META-INF/container.xml` and the OPF exist and parse
+//! - every manifest item exists; ids are unique
+//! - the spine is non-empty and its idrefs (and `toc`) point into the manifest
+//! - XHTML content documents are well-formed
+//! - internal `href`/`src` links resolve to entries in the archive
+//!
+//! DRM-encrypted documents are skipped (with a warning) rather than reported
+//! as malformed.
+
+use anyhow::{Context, Result};
+use roxmltree::{Document, ParsingOptions};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+use zip::{CompressionMethod, ZipArchive};
+
+use crate::container::{Container, DirContainer};
+use crate::metadata::{read_encryption, read_opf, resolve_href};
+use crate::model::ValidationStatus;
+
+/// More than this many issues are summarized in one final line.
+const MAX_ISSUES: usize = 100;
+
+#[derive(Default)]
+struct Issues(Vec<String>);
+
+impl Issues {
+    fn error(&mut self, msg: impl Display) {
+        self.0.push(format!("error: {msg}"));
+    }
+    fn warning(&mut self, msg: impl Display) {
+        self.0.push(format!("warning: {msg}"));
+    }
+}
+
+/// Validate one EPUB (file or exploded directory); returns its status and
+/// issues ("error: …" / "warning: …").
+pub fn validate_epub(path: &Path) -> (ValidationStatus, Vec<String>) {
+    if path.is_dir() {
+        return finish(|v| check_dir(path, v));
+    }
+    validate_with(|| File::open(path).with_context(|| format!("open {}", path.display())))
+}
+
+/// `validate_epub` for any seekable source, opened by `open` (e.g. a remote
+/// file read in ranges).
+pub fn validate_with<R: Read + Seek>(
+    open: impl FnOnce() -> Result<R>,
+) -> (ValidationStatus, Vec<String>) {
+    finish(|v| open().and_then(|r| check(r, v)))
+}
+
+/// Run `run`, count a hard failure as one more error, and grade the result.
+fn finish(run: impl FnOnce(&mut Issues) -> Result<()>) -> (ValidationStatus, Vec<String>) {
+    let mut v = Issues::default();
+    if let Err(e) = run(&mut v) {
+        v.error(format!("{e:#}"));
+    }
+    let mut issues = v.0;
+    if issues.len() > MAX_ISSUES {
+        let more = issues.len() - MAX_ISSUES;
+        issues.truncate(MAX_ISSUES);
+        issues.push(format!("… and {more} more issue(s)"));
+    }
+    let status = if issues.iter().any(|i| i.starts_with("error:")) {
+        ValidationStatus::Invalid
+    } else if issues.is_empty() {
+        ValidationStatus::Valid
+    } else {
+        ValidationStatus::Warnings
+    };
+    (status, issues)
+}
+
+fn check<R: Read + Seek>(r: R, v: &mut Issues) -> Result<()> {
+    let mut zip = ZipArchive::new(r).context("not a readable zip archive")?;
+
+    // 1) OCF mimetype
+    // (is first in the file, compression, content, has extra field)
+    let first = zip.by_index_raw(0).ok().map(|mut f| {
+        let first = f.name() == "mimetype" && f.header_start() == 0;
+        let mut s = String::new();
+        f.read_to_string(&mut s).ok();
+        let extra = f.extra_data().is_some_and(|d| !d.is_empty());
+        (first, f.compression(), s, extra)
+    });
+    match first {
+        Some((true, method, content, extra)) => {
+            if method != CompressionMethod::Stored {
+                v.error("mimetype is compressed (must be stored)");
+            } else if content != "application/epub+zip" {
+                v.error(format!(
+                    "mimetype reads {content:?}, expected \"application/epub+zip\""
+                ));
+            }
+            if extra {
+                v.warning("mimetype entry has an extra field");
+            }
+        }
+        Some(_) if zip.index_for_name("mimetype").is_some() => {
+            v.error("mimetype is not the first entry")
+        }
+        Some(_) => v.error("mimetype entry is missing"),
+        None => v.error("archive is empty"),
+    }
+    check_content(&mut zip, v)
+}
+
+fn check_dir(path: &Path, v: &mut Issues) -> Result<()> {
+    let mut dir = DirContainer::new(path);
+    // 1) OCF mimetype; entry order and compression do not apply
+    match dir.read_string("mimetype") {
+        Ok(content) if content == "application/epub+zip" => {}
+        Ok(content) => v.error(format!(
+            "mimetype reads {content:?}, expected \"application/epub+zip\""
+        )),
+        Err(_) => v.error("mimetype entry is missing"),
+    }
+    check_content(&mut dir, v)
+}
+
+/// Steps 2–6, shared by zipped and exploded books.
+fn check_content<C: Container>(c: &mut C, v: &mut Issues) -> Result<()> {
+    // 2) container.xml → OPF
+    let names: HashSet<String> = c.names().into_iter().collect();
+    let (opf_path, opf_xml) = match read_opf(c) {
+        Ok(x) => x,
+        Err(e) => {
+            v.error(format!("{e:#}"));
+            return Ok(());
+        }
+    };
+    let opf = match Document::parse(&opf_xml) {
+        Ok(d) => d,
+        Err(e) => {
+            v.error(format!("OPF {opf_path} is not well-formed: {e}"));
+            return Ok(());
+        }
+    };
+
+    // Encrypted (DRM) documents cannot be parsed; say so instead of flagging them.
+    let enc = read_encryption(c);
+    let encrypted: HashSet<&String> = enc.encrypted.iter().collect();
+    if let Some(drm) = enc.drm {
+        v.warning(format!(
+            "DRM ({}): {} encrypted resource(s) not checked",
+            drm.as_str(),
+            encrypted.len()
+        ));
+    }
+
+    // 3) manifest: id → (entry name, media type); remote resources have no entry
+    let mut manifest: HashMap<&str, (Option<String>, &str)> = HashMap::new();
+    let items = opf.descendants().filter(|n| {
+        n.has_tag_name("item") && n.parent().is_some_and(|p| p.has_tag_name("manifest"))
+    });
+    for item in items {
+        let Some(id) = item.attribute("id") else {
+            v.error("manifest item without id");
+            continue;
+        };
+        let Some(href) = item.attribute("href") else {
+            v.error(format!("manifest item {id:?} has no href"));
+            continue;
+        };
+        let name = (!is_external(href)).then(|| resolve_href(&opf_path, href));
+        if let Some(n) = &name
+            && !names.contains(n)
+        {
+            v.error(format!("manifest item {id:?}: {n} not found in archive"));
+        }
+        let media_type = item.attribute("media-type").unwrap_or_default();
+        if manifest.insert(id, (name, media_type)).is_some() {
+            v.error(format!("duplicate manifest id {id:?}"));
+        }
+    }
+
+    // 4) spine
+    match opf.descendants().find(|n| n.has_tag_name("spine")) {
+        None => v.error("OPF has no <spine>"),
+        Some(spine) => {
+            let mut refs = 0;
+            for idref in spine
+                .children()
+                .filter(|n| n.has_tag_name("itemref"))
+                .map(|n| n.attribute("idref").unwrap_or_default())
+            {
+                refs += 1;
+                if !manifest.contains_key(idref) {
+                    v.error(format!("spine itemref {idref:?} is not in the manifest"));
+                }
+            }
+            if refs == 0 {
+                v.error("spine is empty");
+            }
+            if let Some(toc) = spine.attribute("toc")
+                && !manifest.contains_key(toc)
+            {
+                v.error(format!("spine toc {toc:?} is not in the manifest"));
+            }
+        }
+    }
+
+    // 5) XHTML well-formedness and 6) internal links
+    let mut docs: Vec<&String> = manifest
+        .values()
+        .filter(|(_, mt)| *mt == "application/xhtml+xml")
+        .filter_map(|(name, _)| name.as_ref())
+        .filter(|n| names.contains(*n) && !encrypted.contains(n))
+        .collect();
+    docs.sort();
+    for name in docs {
+        let bytes = c.read_bytes(name)?;
+        let Ok(text) = String::from_utf8(bytes) else {
+            v.warning(format!("{name}: not UTF-8"));
+            continue;
+        };
+        let opts = ParsingOptions {
+            allow_dtd: true,
+            ..ParsingOptions::default()
+        };
+        let doc = match Document::parse_with_options(&text, opts) {
+            Ok(d) => d,
+            // HTML entities (&nbsp;) are declared by the external XHTML DTD,
+            // which we do not load; EPUB 2 readers accept them.
+            Err(e @ roxmltree::Error::UnknownEntityReference(..)) if text.contains("<!DOCTYPE") => {
+                v.warning(format!("{name}: {e}"));
+                continue;
+            }
+            Err(e) => {
+                v.error(format!("{name}: not well-formed: {e}"));
+                continue;
+            }
+        };
+        let mut broken: Vec<String> = doc
+            .descendants()
+            .filter(|n| n.is_element())
+            .flat_map(|n| n.attributes())
+            .filter(|a| matches!(a.name(), "href" | "src"))
+            .map(|a| a.value().trim())
+            .filter(|href| !href.is_empty() && !href.starts_with('#') && !is_external(href))
+            .map(|href| resolve_href(name, href))
+            .filter(|target| !names.contains(target))
+            .collect();
+        broken.sort();
+        broken.dedup();
+        for target in broken {
+            v.error(format!("{name}: broken link to {target}"));
+        }
+    }
+    Ok(())
+}
+
+/// Has a URI scheme (http:, mailto:, data:, …), i.e. not a path inside the book.
+fn is_external(href: &str) -> bool {
+    href.split(['/', '?', '#'])
+        .next()
+        .is_some_and(|first| first.contains(':'))
+}
@@ -0,0 +1,100 @@
+//! Volume identity: which disk an entry lives on, so an unplugged disk reads
+//! as "volume offline" rather than thousands of missing files.
+//!
+//! A volume is identified by, in order of preference:
+//! - a marker file (`.epubr-volume`, first line = label) in the directory or
+//!   any ancestor → `marker:<label>`
+//! - the filesystem UUID of the device holding the path (Linux,
+//!   `/dev/disk/by-uuid`) → `uuid:<uuid>`
+//!
+//! An entry's volume is offline when the volume now found at its path (or the
+//! nearest existing ancestor, e.g. an empty mountpoint) differs from the one
+//! recorded.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::model::BookEntry;
+
+/// Marker file name; its first non-empty line is the volume label.
+pub const MARKER_FILENAME: &str = ".epubr-volume";
+
+/// Memoizing volume lookups (marker search per directory, device → UUID).
+#[derive(Debug, Default)]
+pub struct Volumes {
+    markers: HashMap<PathBuf, Option<String>>,
+    uuids: Option<HashMap<u64, String>>,
+}
+
+impl Volumes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Volume id for `p`, or for its nearest existing ancestor if it is gone.
+    pub fn volume_of(&mut self, p: &Path) -> Option<String> {
+        let dir = p
+            .ancestors()
+            .skip(if p.is_dir() { 0 } else { 1 })
+            .find(|a| a.is_dir())?;
+        if let Some(label) = self.marker(dir) {
+            return Some(format!("marker:{label}"));
+        }
+        self.uuid(dir).map(|u| format!("uuid:{u}"))
+    }
+
+    /// True if the entry records a volume and a different one (or none) is
+    /// currently at its path.
+    pub fn is_offline(&mut self, e: &BookEntry) -> bool {
+        match &e.volume {
+            Some(v) => self.volume_of(Path::new(&e.full_path)).as_ref() != Some(v),
+            None => false,
+        }
+    }
+
+    fn marker(&mut self, dir: &Path) -> Option<String> {
+        if let Some(hit) = self.markers.get(dir) {
+            return hit.clone();
+        }
+        let own = fs::read_to_string(dir.join(MARKER_FILENAME))
+            .ok()
+            .and_then(|s| {
+                s.lines()
+                    .map(str::trim)
+                    .find(|l| !l.is_empty())
+                    .map(String::from)
+            });
+        let found = match own {
+            Some(l) => Some(l),
+            None => dir.parent().and_then(|p| self.marker(p)),
+        };
+        self.markers.insert(dir.to_path_buf(), found.clone());
+        found
+    }
+
+    #[cfg(unix)]
+    fn uuid(&mut self, dir: &Path) -> Option<String> {
+        use std::os::unix::fs::MetadataExt;
+
+        let uuids = self.uuids.get_or_insert_with(|| {
+            let mut m = HashMap::new();
+            if let Ok(rd) = fs::read_dir("/dev/disk/by-uuid") {
+                for ent in rd.flatten() {
+                    // Symlink → block device; its rdev is the st_dev of files on it.
+                    if let Ok(md) = fs::metadata(ent.path()) {
+                        m.insert(md.rdev(), ent.file_name().to_string_lossy().to_string());
+                    }
+                }
+            }
+            m
+        });
+        let dev = fs::metadata(dir).ok()?.dev();
+        uuids.get(&dev).cloned()
+    }
+
+    #[cfg(not(unix))]
+    fn uuid(&mut self, _dir: &Path) -> Option<String> {
+        None
+    }
+}